@@ -0,0 +1,72 @@
+#import bevy_sprite::mesh2d_types
+#import bevy_sprite::mesh2d_view_bindings
+
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping
+#endif
+
+// NOTE: these must match `PixelLayout` in src/camera.rs
+const LAYOUT_RGBA: u32 = 0u;
+const LAYOUT_YUYV: u32 = 1u;
+const LAYOUT_NV12: u32 = 2u;
+
+@group(1) @binding(0)
+var<uniform> pixel_layout: u32;
+@group(1) @binding(1)
+var texture: texture_2d<f32>;
+@group(1) @binding(2)
+var texture_sampler: sampler;
+
+@group(2) @binding(0)
+var<uniform> mesh: Mesh2d;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+// the camera's values are gamma encoded, but the render target expects linear color
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// BT.601 with the limited range that UVC cameras send
+fn yuv_to_rgb(y: f32, u: f32, v: f32) -> vec3<f32> {
+    let luma = (y - 16.0 / 255.0) * 1.164;
+    let d = u - 0.5;
+    let e = v - 0.5;
+    let rgb = vec3<f32>(
+        luma + 1.596 * e,
+        luma - 0.392 * d - 0.813 * e,
+        luma + 2.017 * d,
+    );
+    return srgb_to_linear(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    var output_color = textureSample(texture, texture_sampler, in.uv);
+    let dims = vec2<i32>(textureDimensions(texture));
+    if (pixel_layout == LAYOUT_YUYV) {
+        // every texel holds a pair of pixels as Y0 U Y1 V
+        let size = vec2<i32>(dims.x * 2, dims.y);
+        let pixel = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+        let texel = textureLoad(texture, vec2<i32>(pixel.x / 2, pixel.y), 0);
+        let y = select(texel.r, texel.b, pixel.x % 2 == 1);
+        output_color = vec4<f32>(yuv_to_rgb(y, texel.g, texel.a), 1.0);
+    } else if (pixel_layout == LAYOUT_NV12) {
+        // a square luma plane with half as many rows of interleaved U and V below it
+        let side = dims.x;
+        let pixel = clamp(vec2<i32>(in.uv * f32(side)), vec2<i32>(0), vec2<i32>(side - 1));
+        let y = textureLoad(texture, pixel, 0).r;
+        let chroma = vec2<i32>(pixel.x / 2 * 2, side + pixel.y / 2);
+        let u = textureLoad(texture, chroma, 0).r;
+        let v = textureLoad(texture, chroma + vec2<i32>(1, 0), 0).r;
+        output_color = vec4<f32>(yuv_to_rgb(y, u, v), 1.0);
+    }
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+    return output_color;
+}
@@ -1,4 +1,6 @@
 use crate::bluetooth::RotationInterval;
+use crate::record::Soundtrack;
+use crate::rotation::{directed_speed, playback_rate, ResponseCurve};
 use crate::setup::Settings;
 use crate::zoetrope::{RotationDirection, ZoetropeAnimationThresholdSpeed};
 use bevy::prelude::*;
@@ -9,18 +11,58 @@ pub struct Song(pub String);
 
 pub struct VolumeEvent(pub u8);
 
-pub fn audio_setup(server: Res<AssetServer>, audio: Res<Audio>, settings: Res<Settings>) {
+/// The song that is playing, kept so that recordings can render it at the rate it was played.
+#[derive(Resource)]
+pub struct PlayingSong {
+    pub source: Handle<AudioSource>,
+    pub instance: Handle<AudioInstance>,
+}
+
+impl PlayingSong {
+    /// The decoded song and how far into it playback is, once it has loaded and started.
+    pub fn soundtrack(
+        &self,
+        sources: &Assets<AudioSource>,
+        instances: &Assets<AudioInstance>,
+    ) -> Option<Soundtrack> {
+        let position = match instances.get(&self.instance)?.state() {
+            PlaybackState::Playing { position }
+            | PlaybackState::Pausing { position }
+            | PlaybackState::Paused { position } => position,
+            _ => return None,
+        };
+        Some(Soundtrack {
+            sound: sources.get(&self.source)?.sound.clone(),
+            start: position,
+        })
+    }
+}
+
+pub fn audio_setup(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    audio: Res<Audio>,
+    settings: Res<Settings>,
+) {
     match &settings.song {
         Some(music) => {
-            audio
-                .play(server.load(format!("audio/{}", music)))
+            let source = server.load(format!("audio/{}", music));
+            let instance = audio
+                .play(source.clone())
                 .looped()
-                .with_volume(0.5);
+                .with_volume(0.5)
+                .handle();
+            commands.insert_resource(PlayingSong { source, instance });
         }
         None => {}
     }
 }
 
+pub fn audio_teardown(mut commands: Commands, audio: Res<Audio>) {
+    audio.stop();
+    commands.remove_resource::<PlayingSong>();
+}
+
 #[allow(dead_code)]
 pub fn audio_modulation_keyboard(input: Res<Input<KeyCode>>, audio: Res<Audio>) {
     let mut rate: f64 = 1.0;
@@ -41,15 +83,9 @@ pub fn audio_modulation_rotation(
     max: Res<ZoetropeAnimationThresholdSpeed>,
     audio: Res<Audio>,
     dir: Res<RotationDirection>,
+    curve: Res<ResponseCurve>,
 ) {
-    let val: f64;
-    if rotation.0 >= max.0 {
-        val = (!dir.audio * 1.0) as f64;
-    } else if rotation.0 <= -max.0 {
-        val = (dir.audio * 1.0) as f64;
-    } else {
-        val = rotation.0 as f64 / max.0 as f64;
-    }
+    let val = playback_rate(directed_speed(rotation.0, max.0, dir.audio, &curve));
     audio
         .set_playback_rate(val)
         .linear_fade_in(std::time::Duration::from_secs_f64(1.0 / max.0 as f64));
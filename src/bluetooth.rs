@@ -21,8 +21,13 @@ pub struct RotationInterval(pub i8); // Converted rotation value for use in exte
 pub struct ArduinoConnected(pub bool);
 
 // systems
-pub fn async_converter_arduino_reader(rt: Res<TokioTasksRuntime>) {
-    rt.spawn_background_task(get_bluetooth_data);
+pub fn async_converter_arduino_reader(rt: Res<TokioTasksRuntime>, mut started: Local<bool>) {
+    // the reader keeps going for the life of the app, so coming back from the setup menu must not
+    // start a second one
+    if !*started {
+        *started = true;
+        rt.spawn_background_task(get_bluetooth_data);
+    }
 }
 
 pub fn async_converter_arduino_finder(rt: Res<TokioTasksRuntime>) {
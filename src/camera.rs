@@ -1,126 +1,602 @@
 use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 // This is for the handling of the physical camera and its implementation of the overall controlling within the other modules.
 use anyhow::Result;
 use bevy::prelude::*;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::utils::HashMap;
-use flume::{bounded, unbounded};
+use flume::{bounded, unbounded, TrySendError};
 use image::{ImageBuffer, Rgba};
 use nokhwa::pixel_format::RgbAFormat;
 use nokhwa::query;
 use nokhwa::utils::{
-    ApiBackend, CameraControl, CameraIndex, ControlValueSetter, KnownCameraControl, RequestedFormat,
+    ApiBackend, CameraControl, CameraFormat, CameraIndex, ControlValueDescription,
+    ControlValueSetter, FrameFormat, KnownCameraControl, RequestedFormat, RequestedFormatType,
+    Resolution,
 };
 use nokhwa::Camera;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_config, save_config};
+use crate::decode::DecodePool;
+use crate::pattern::TestPattern;
+use crate::playback::Playback;
+use crate::stats::CaptureCounters;
 
 #[derive(Component)]
 pub struct VideoStream {
-    pub image_rx: flume::Receiver<Image>,
-    pub op_tx: flume::Sender<CameraSetting>,
+    pub image_rx: flume::Receiver<Frame>,
+    pub op_tx: flume::Sender<SourceOp>,
+    pub status_rx: flume::Receiver<SourceStatus>,
+    // the controls of whatever device came back after a reconnect, which may not be the same one
+    controls_rx: flume::Receiver<BTreeMap<KnownCameraControl, CameraControl>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
     pub known_controls: BTreeMap<KnownCameraControl, CameraControl>,
     pub controls: BTreeMap<KnownCameraControl, ControlValueSetter>,
+    // the format that was actually negotiated with the device, `None` for sources that are not cameras
+    pub format: Option<CameraFormat>,
+    pub crop: CropRegion,
+    pub counters: Arc<CaptureCounters>,
+}
+
+/// A single image from a source, stamped so that its trip through the pipeline can be timed.
+pub struct Frame {
+    pub image: Image,
+    pub layout: PixelLayout,
+    pub captured_at: Instant,
+    pub decode_time: Duration,
+}
+
+impl Frame {
+    // for sources where the image is ready as soon as it is captured
+    pub fn now(image: Image) -> Self {
+        Self {
+            image,
+            layout: PixelLayout::Rgba,
+            captured_at: Instant::now(),
+            decode_time: Duration::ZERO,
+        }
+    }
+}
+
+/// How the bytes of a [`Frame`] are laid out. Anything other than `Rgba` is converted by the zoetrope's
+/// shader instead of on the cpu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelLayout {
+    #[default]
+    Rgba = 0,
+    // half width rgba texels each holding two pixels as Y0 U Y1 V
+    Yuyv = 1,
+    // a single channel texture with the luma plane on top and the interleaved chroma plane below it
+    Nv12 = 2,
+}
+
+impl PixelLayout {
+    /// The layout frames of the given format are uploaded in.
+    pub fn of(format: FrameFormat) -> Self {
+        match format {
+            FrameFormat::YUYV => Self::Yuyv,
+            FrameFormat::NV12 => Self::Nv12,
+            _ => Self::Rgba,
+        }
+    }
+}
+
+/// A frame arrived from the source but could not be turned into an image. Unlike other errors this does
+/// not mean the source has gone away, so the frame is skipped instead of reconnecting.
+#[derive(Debug)]
+pub struct DecodeError(pub String);
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Couldn't decode the frame: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The square that is cut out of each camera frame to be shown on the zoetrope, described relative to
+/// the center of the frame so that it stays meaningful if the resolution changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CropRegion {
+    pub size: u32,
+    pub offset: (i32, i32),
+}
+
+impl CropRegion {
+    /// The largest square that fits in the middle of a frame of the given resolution.
+    pub fn centered(resolution: Resolution) -> Self {
+        Self {
+            size: resolution.width().min(resolution.height()),
+            offset: (0, 0),
+        }
+    }
+
+    /// The left, top and side length of the square in a frame of the given resolution, clamped so that
+    /// the square never leaves the frame.
+    pub fn rect(&self, resolution: Resolution) -> (u32, u32, u32) {
+        let (w, h) = (resolution.width(), resolution.height());
+        let side = self.size.clamp(1, w.min(h));
+        let x = (w as i64 / 2 - side as i64 / 2 + self.offset.0 as i64).clamp(0, (w - side) as i64);
+        let y = (h as i64 / 2 - side as i64 / 2 + self.offset.1 as i64).clamp(0, (h - side) as i64);
+        (x as u32, y as u32, side)
+    }
+}
+
+// the crops that have been chosen for each camera, keyed by the camera name
+const CROPS_FILE: &str = "crops.ron";
+
+pub fn load_crop(camera: &str) -> Option<CropRegion> {
+    load_config::<HashMap<String, CropRegion>>(CROPS_FILE)?
+        .get(camera)
+        .copied()
+}
+
+pub fn save_crop(camera: &str, crop: CropRegion) -> Result<()> {
+    let mut crops: HashMap<String, CropRegion> = load_config(CROPS_FILE).unwrap_or_default();
+    crops.insert(camera.to_string(), crop);
+    save_config(CROPS_FILE, &crops)
 }
 
-#[derive(Resource)]
+// controls that nokhwa does not have a name for, by their V4L2 id
+pub const PAN_ABSOLUTE: KnownCameraControl = KnownCameraControl::Other(10094856);
+pub const TILT_ABSOLUTE: KnownCameraControl = KnownCameraControl::Other(10094857);
+pub const ZOOM_ABSOLUTE: KnownCameraControl = KnownCameraControl::Other(10094861);
+pub const EXPOSURE_AUTO: KnownCameraControl = KnownCameraControl::Other(10094849);
+pub const EXPOSURE_ABSOLUTE: KnownCameraControl = KnownCameraControl::Other(10094850);
+pub const AUTO_WHITE_BALANCE: KnownCameraControl = KnownCameraControl::Other(9963788);
+pub const WHITE_BALANCE_TEMPERATURE: KnownCameraControl = KnownCameraControl::Other(9963802);
+pub const GAIN: KnownCameraControl = KnownCameraControl::Other(9963795);
+
+// depending on the backend these are reported either by name or by their V4L2 id
+pub const EXPOSURE_CONTROLS: [KnownCameraControl; 2] =
+    [KnownCameraControl::Exposure, EXPOSURE_ABSOLUTE];
+pub const WHITE_BALANCE_CONTROLS: [KnownCameraControl; 2] =
+    [KnownCameraControl::WhiteBalance, WHITE_BALANCE_TEMPERATURE];
+pub const GAIN_CONTROLS: [KnownCameraControl; 2] = [KnownCameraControl::Gain, GAIN];
+
+// values of the V4L2 auto exposure menu
+const EXPOSURE_MANUAL: i64 = 1;
+const EXPOSURE_APERTURE_PRIORITY: i64 = 3;
+
+// values are kept as the raw control values, as the ranges differ from camera to camera,
+// and are filled in from the camera's own defaults once it is opened
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ColorSettings {
-    pub brightness: i8,
-    pub contrast: u8,
-    pub saturation: u8,
-    pub gamma: u16,
-    pub gain: u8,
-    pub white_balance: u32,
-    pub sharpness: u8,
+    pub brightness: i64,
+    pub contrast: i64,
+    pub saturation: i64,
+    pub gamma: i64,
+    pub gain: i64,
+    pub white_balance: i64,
+    pub auto_white_balance: bool,
+    pub sharpness: i64,
     pub auto_exposure: bool,
-    pub zoom: u16,
-    pub tilt: i32,
-    pub pan: i32,
+    // in units of 100us, only used while auto exposure is off
+    pub exposure: i64,
+    pub zoom: i64,
+    pub tilt: i64,
+    pub pan: i64,
 }
 
-impl Default for ColorSettings {
-    fn default() -> Self {
-        Self {
-            brightness: 0,
-            contrast: 15,
-            saturation: 32,
-            gamma: 220,
-            gain: 0,
-            white_balance: 5000,
-            sharpness: 16,
-            auto_exposure: true,
-            zoom: 100,
-            tilt: 0,
-            pan: 0,
+impl ColorSettings {
+    fn field(&mut self, id: KnownCameraControl) -> Option<&mut i64> {
+        match id {
+            KnownCameraControl::Brightness => Some(&mut self.brightness),
+            KnownCameraControl::Contrast => Some(&mut self.contrast),
+            KnownCameraControl::Saturation => Some(&mut self.saturation),
+            KnownCameraControl::Gamma => Some(&mut self.gamma),
+            KnownCameraControl::Sharpness => Some(&mut self.sharpness),
+            ZOOM_ABSOLUTE => Some(&mut self.zoom),
+            TILT_ABSOLUTE => Some(&mut self.tilt),
+            PAN_ABSOLUTE => Some(&mut self.pan),
+            _ if EXPOSURE_CONTROLS.contains(&id) => Some(&mut self.exposure),
+            _ if WHITE_BALANCE_CONTROLS.contains(&id) => Some(&mut self.white_balance),
+            _ if GAIN_CONTROLS.contains(&id) => Some(&mut self.gain),
+            _ => None,
+        }
+    }
+
+    // records a control that was changed, if it is one of the ones kept here
+    pub fn set(&mut self, id: KnownCameraControl, value: i64) {
+        match id {
+            EXPOSURE_AUTO => self.auto_exposure = value != EXPOSURE_MANUAL,
+            AUTO_WHITE_BALANCE => self.auto_white_balance = value != 0,
+            _ => {
+                if let Some(field) = self.field(id) {
+                    *field = value;
+                }
+            }
         }
     }
+
+    // every integer control and the value it should be set to
+    pub fn values(&self) -> [(KnownCameraControl, i64); 8] {
+        [
+            (KnownCameraControl::Brightness, self.brightness),
+            (KnownCameraControl::Contrast, self.contrast),
+            (KnownCameraControl::Saturation, self.saturation),
+            (KnownCameraControl::Gamma, self.gamma),
+            (KnownCameraControl::Sharpness, self.sharpness),
+            (ZOOM_ABSOLUTE, self.zoom),
+            (TILT_ABSOLUTE, self.tilt),
+            (PAN_ABSOLUTE, self.pan),
+        ]
+    }
+}
+
+// what the zoetrope should be fed with, picked in the setup menu
+#[derive(Debug, Clone, PartialEq)]
+pub enum VideoSource {
+    Camera(String, u32),
+    TestPattern,
+    Playback(PathBuf),
 }
 
+impl VideoSource {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Camera(name, _) => name.clone(),
+            Self::TestPattern => "Test Pattern".to_string(),
+            Self::Playback(path) => format!(
+                "Playback: {}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
+
+    pub fn open(&self, format: RequestedFormat, slices: u8) -> Result<VideoStream> {
+        match self {
+            Self::Camera(name, index) => {
+                let mut stream =
+                    VideoStream::new(NokhwaSource::new(CameraIndex::Index(*index), format)?)?;
+                if let Some(crop) = load_crop(name) {
+                    stream.set_crop(crop);
+                }
+                Ok(stream)
+            }
+            Self::TestPattern => VideoStream::new(TestPattern::new(slices)),
+            Self::Playback(path) => VideoStream::new(Playback::new(path)),
+        }
+    }
+}
+
+// everything the main thread can ask of the thread running a source
+pub enum SourceOp {
+    Control(CameraSetting),
+    Advance(f32),
+    Crop(CropRegion),
+    Slices(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceStatus {
+    Streaming,
+    // the source failed and is being closed and opened again until it works
+    Reconnecting {
+        error: String,
+        attempt: u32,
+        retry_in: Duration,
+    },
+}
+
+const RECONNECT_MIN_WAIT: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_WAIT: Duration = Duration::from_secs(10);
+// how long stopping waits on the source thread, a camera that was unplugged can leave it stuck for good
+const STOP_WAIT: Duration = Duration::from_secs(2);
+
 pub struct CameraSetting {
     pub id: KnownCameraControl,
     pub control: ControlValueSetter,
 }
 
-impl VideoStream {
+/// A producer of frames for the zoetrope, driven by the capture thread that [`VideoStream`] owns.
+pub trait FrameSource: Send + 'static {
+    /// Starts the underlying device or file so that frames can be pulled from it. This is called
+    /// again after [`FrameSource::close`] when the source has to be reconnected.
+    fn open(&mut self) -> Result<()>;
+    /// Stops the underlying device so that it can be opened again.
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Produces the next frame, or `None` when there is nothing new to show yet. Returning a
+    /// [`DecodeError`] skips the frame rather than reconnecting.
+    fn next_frame(&mut self) -> Result<Option<Frame>>;
+    /// Applies a single control change (brightness, zoom, etc.) to the source.
+    fn apply_control(&mut self, setting: CameraSetting) -> Result<()>;
+    /// Lists the controls the source supports along with their current values.
+    fn controls(&self) -> Result<BTreeMap<KnownCameraControl, CameraControl>>;
+    /// Moves a pre-recorded source on by a (possibly fractional or negative) number of frames.
+    /// Live sources ignore this.
+    fn advance(&mut self, _frames: f32) {}
+    /// The format negotiated with the device, for sources that have one.
+    fn format(&self) -> Option<CameraFormat> {
+        None
+    }
+    /// Changes the part of each frame that is kept. Sources that already produce square frames ignore
+    /// this.
+    fn set_crop(&mut self, _crop: CropRegion) {}
+    /// Called when the number of slices changes, for sources that draw them.
+    fn set_slices(&mut self, _slices: u8) {}
+    /// Gives a source that decodes frames in the background the counters, so it can count each
+    /// frame as captured when it is grabbed. Returns false when the capture thread should count the
+    /// frames it is handed instead.
+    fn share_counters(&mut self, _counters: &Arc<CaptureCounters>) -> bool {
+        false
+    }
+}
+
+// a physical camera that is read through nokhwa
+pub struct NokhwaSource {
+    index: CameraIndex,
+    camera: Camera,
+    crop: Option<CropRegion>,
+    closed: bool,
+    // frames are decoded here so that the capture thread only has to grab them
+    decoder: DecodePool,
+    counters: Arc<CaptureCounters>,
+}
+
+impl NokhwaSource {
     pub fn new(index: CameraIndex, format: RequestedFormat) -> Result<Self> {
+        Ok(Self {
+            camera: Camera::new(index.clone(), format)?,
+            index,
+            crop: None,
+            closed: false,
+            decoder: DecodePool::new(),
+            counters: Arc::default(),
+        })
+    }
+}
+
+impl FrameSource for NokhwaSource {
+    fn open(&mut self) -> Result<()> {
+        if self.closed {
+            // the device may have dropped off the bus, so it is found again from scratch in the format
+            // that was negotiated the first time
+            let format = self.camera.camera_format();
+            self.camera = Camera::new(
+                self.index.clone(),
+                RequestedFormat::new::<RgbAFormat>(RequestedFormatType::Exact(format)),
+            )?;
+            self.closed = false;
+            // anything still being decoded came from before the device went away
+            self.decoder = DecodePool::new();
+        }
+        self.camera.open_stream()?;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.closed = true;
+        self.camera.stop_stream()?;
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        // anything that finished while the camera was being waited on goes out before waiting on it again
+        if let Some(result) = self.decoder.next_decoded(Instant::now()) {
+            return Ok(Some(result?));
+        }
+        let buffer = self.camera.frame()?;
+        let captured_at = Instant::now();
+        // until told otherwise, use the biggest square the negotiated format allows
+        let crop = self
+            .crop
+            .unwrap_or_else(|| CropRegion::centered(buffer.resolution()))
+            .rect(buffer.resolution());
+        self.decoder.submit(buffer, crop, captured_at);
+        self.counters.captured.fetch_add(1, Ordering::Relaxed);
+        // the frame is waited on until the camera has the next one ready, if it takes any longer it is
+        // left to the workers while that one is captured
+        let fps = self.camera.camera_format().frame_rate().max(1);
+        let interval = Duration::from_secs_f64(1. / fps as f64);
+        match self.decoder.next_decoded(captured_at + interval) {
+            Some(Ok(frame)) => Ok(Some(frame)),
+            Some(Err(why)) => Err(why.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn apply_control(&mut self, setting: CameraSetting) -> Result<()> {
+        self.camera
+            .set_camera_control(setting.id, setting.control)?;
+        Ok(())
+    }
+
+    fn controls(&self) -> Result<BTreeMap<KnownCameraControl, CameraControl>> {
+        Ok(self
+            .camera
+            .camera_controls_known_camera_controls()?
+            .into_iter()
+            .collect())
+    }
+
+    fn format(&self) -> Option<CameraFormat> {
+        Some(self.camera.camera_format())
+    }
+
+    fn set_crop(&mut self, crop: CropRegion) {
+        self.crop = Some(crop);
+    }
+
+    fn share_counters(&mut self, counters: &Arc<CaptureCounters>) -> bool {
+        self.counters = counters.clone();
+        true
+    }
+}
+
+impl VideoStream {
+    pub fn new<S: FrameSource>(mut source: S) -> Result<Self> {
         // lots of this is *heavily* taken from https://github.com/foxzool/bevy_nokhwa/blob/main/src/camera.rs
         let (sender, receiver) = bounded(1);
-        let (op_tx, op_rx) = unbounded::<CameraSetting>();
+        let (op_tx, op_rx) = unbounded::<SourceOp>();
+        let (status_tx, status_rx) = unbounded::<SourceStatus>();
+        let (controls_tx, controls_rx) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let counters = Arc::new(CaptureCounters::default());
+        let thread_counters = counters.clone();
+        let counts_captured = !source.share_counters(&counters);
 
-        let mut cam = Camera::new(index, format).unwrap();
+        source.open()?;
+        let known_controls = source.controls()?;
+        let format = source.format();
 
-        cam.open_stream().expect("Could not open the camera stream");
-        let known_controls = cam.camera_controls_known_camera_controls().unwrap();
+        // kept by the thread so that a frame nobody took yet can be swapped for a newer one
+        let stale = receiver.clone();
 
-        std::thread::spawn(move || {
-            #[allow(clippy::empty_loop)]
-            loop {
-                match op_rx.try_recv() {
-                    Ok(op) => {
-                        if let Err(why) = cam.set_camera_control(op.id, op.control) {
-                            eprintln!("Couldn't set the control: {}", why);
+        let thread = std::thread::spawn(move || {
+            // the last value of every control, so that they can be put back after reconnecting
+            let mut applied = BTreeMap::new();
+            while !thread_stop.load(Ordering::Relaxed) {
+                for op in op_rx.try_iter() {
+                    match op {
+                        SourceOp::Control(setting) => {
+                            applied.insert(setting.id, setting.control.clone());
+                            if let Err(why) = source.apply_control(setting) {
+                                eprintln!("Couldn't set the control: {}", why);
+                            }
                         }
+                        SourceOp::Advance(frames) => source.advance(frames),
+                        SourceOp::Crop(crop) => source.set_crop(crop),
+                        SourceOp::Slices(slices) => source.set_slices(slices),
                     }
-                    // Err(why) => eprintln!("couldn't receive: {}", why),
-                    Err(_why) => (),
                 }
-                let buffer = cam.frame().expect("Couldn't receive the camera frame");
-                let mut buf = buffer.decode_image::<RgbAFormat>().unwrap();
-                let wh = (2160, 2160);
-                let image = Self::make_image(wh, &mut buf);
-                let _ = sender.send(image);
+                match source.next_frame() {
+                    Ok(Some(frame)) => {
+                        if counts_captured {
+                            thread_counters.captured.fetch_add(1, Ordering::Relaxed);
+                        }
+                        thread_counters.decoded.fetch_add(1, Ordering::Relaxed);
+                        if let Err(TrySendError::Full(frame)) = sender.try_send(frame) {
+                            if stale.try_recv().is_ok() {
+                                thread_counters.dropped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            let _ = sender.try_send(frame);
+                        }
+                    }
+                    // the source is pacing itself, so give it a moment before asking again
+                    Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+                    // a single corrupt frame is not worth tearing the device down for
+                    Err(why) if why.is::<DecodeError>() => {
+                        if counts_captured {
+                            thread_counters.captured.fetch_add(1, Ordering::Relaxed);
+                        }
+                        eprintln!("{}", why);
+                    }
+                    Err(why) => {
+                        eprintln!("Couldn't receive the camera frame: {}", why);
+                        if !reconnect(&mut source, &status_tx, &thread_stop, why.to_string()) {
+                            break;
+                        }
+                        match source.controls() {
+                            Ok(controls) => {
+                                // only what the device that came back actually has is put back
+                                applied.retain(|id, _| controls.contains_key(id));
+                                let _ = controls_tx.send(controls);
+                            }
+                            Err(why) => eprintln!("Couldn't list the controls: {}", why),
+                        }
+                        for (id, control) in applied.iter() {
+                            let setting = CameraSetting {
+                                id: *id,
+                                control: control.clone(),
+                            };
+                            if let Err(why) = source.apply_control(setting) {
+                                eprintln!("Couldn't restore the control: {}", why);
+                            }
+                        }
+                    }
+                }
+            }
+            // let go of the device so that it can be opened again straight away
+            if let Err(why) = source.close() {
+                eprintln!("Couldn't close the video source: {}", why);
             }
         });
 
-        let known_controls: BTreeMap<KnownCameraControl, CameraControl> = known_controls
-            .into_iter()
-            .map(|(k, cont)| (k, cont))
-            .collect();
-
-        let controls = known_controls
-            .iter()
-            .map(|(k, cont)| {
-                let value = cont.value();
-                (*k, value)
-            })
-            .collect();
         Ok(Self {
             image_rx: receiver,
             op_tx,
+            status_rx,
+            controls_rx,
+            controls: control_values(&known_controls),
             known_controls,
-            controls,
+            format,
+            crop: format
+                .map(|f| CropRegion::centered(f.resolution()))
+                .unwrap_or_default(),
+            counters,
+            stop,
+            thread: Some(thread),
         })
     }
 
+    /// Picks up the controls of the device after it reconnected, returning whether they changed.
+    pub fn refresh_controls(&mut self) -> bool {
+        let Some(known_controls) = self.controls_rx.drain().last() else {
+            return false;
+        };
+        self.controls = control_values(&known_controls);
+        self.known_controls = known_controls;
+        true
+    }
+
+    // changes the crop for every frame from here on, saving it for next time is up to the caller
+    pub fn set_crop(&mut self, crop: CropRegion) {
+        self.crop = crop;
+        if let Err(why) = self.op_tx.send(SourceOp::Crop(crop)) {
+            eprintln!("{}", why);
+        }
+    }
+
+    pub fn set_slices(&self, slices: u8) {
+        if let Err(why) = self.op_tx.send(SourceOp::Slices(slices)) {
+            eprintln!("{}", why);
+        }
+    }
+
+    // stops the thread and waits a while for it to release the source
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let deadline = Instant::now() + STOP_WAIT;
+            while !thread.is_finished() {
+                if Instant::now() >= deadline {
+                    // dropping the handle leaves the thread to finish on its own if it ever does
+                    warn!("The video source did not stop in time and was left behind");
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            if thread.join().is_err() {
+                eprintln!("The video source thread panicked");
+            }
+        }
+    }
+
     #[inline]
-    fn make_image(wh: (u32, u32), buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) -> Image {
+    pub fn make_image(
+        (x, y, side): (u32, u32, u32),
+        buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> Image {
         Image::new(
             Extent3d {
-                width: wh.0,
-                height: wh.1,
+                width: side,
+                height: side,
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
-            image::imageops::crop(buffer, 360, 0, 2160, 2160)
+            image::imageops::crop(buffer, x, y, side, side)
                 .to_image()
                 .to_vec(),
             TextureFormat::Rgba8UnormSrgb,
@@ -128,11 +604,97 @@ impl VideoStream {
     }
 }
 
+// shown when the camera could not give the format that was asked for in the setup menu
+#[derive(Resource, Default)]
+pub struct FormatNotice(pub Option<String>);
+
+pub fn describe_format(format: &CameraFormat) -> String {
+    format!(
+        "{}x{} {}fps {}",
+        format.width(),
+        format.height(),
+        format.frame_rate(),
+        format.format()
+    )
+}
+
+/// Every resolution, frame rate and frame format combination the camera says it supports, biggest and
+/// fastest first.
+pub fn query_camera_formats(index: u32) -> Result<Vec<CameraFormat>> {
+    let mut camera = Camera::new(
+        CameraIndex::Index(index),
+        RequestedFormat::new::<RgbAFormat>(RequestedFormatType::None),
+    )?;
+    let mut formats = camera.compatible_camera_formats()?;
+    formats.sort_by_key(|f| std::cmp::Reverse((f.width() * f.height(), f.frame_rate())));
+    Ok(formats)
+}
+
+fn control_values(
+    known_controls: &BTreeMap<KnownCameraControl, CameraControl>,
+) -> BTreeMap<KnownCameraControl, ControlValueSetter> {
+    known_controls
+        .iter()
+        .map(|(k, cont)| {
+            let value = control_value(cont).unwrap_or_else(|| cont.value());
+            (*k, value)
+        })
+        .collect()
+}
+
+impl Drop for VideoStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// closes and opens the source until it works again, waiting a little longer after each failure. Returns
+// false if the stream was stopped before that happened.
+fn reconnect<S: FrameSource>(
+    source: &mut S,
+    status_tx: &flume::Sender<SourceStatus>,
+    stop: &AtomicBool,
+    mut error: String,
+) -> bool {
+    let mut wait = RECONNECT_MIN_WAIT;
+    for attempt in 1.. {
+        let _ = status_tx.send(SourceStatus::Reconnecting {
+            error: error.clone(),
+            attempt,
+            retry_in: wait,
+        });
+        // waited out in small steps so that stopping the stream is not held up
+        let until = Instant::now() + wait;
+        while Instant::now() < until {
+            if stop.load(Ordering::Relaxed) {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        if let Err(why) = source.close() {
+            eprintln!("Couldn't close the video source: {}", why);
+        }
+        match source.open() {
+            Ok(()) => {
+                let _ = status_tx.send(SourceStatus::Streaming);
+                return true;
+            }
+            Err(why) => {
+                eprintln!("Couldn't reopen the video source: {}", why);
+                error = why.to_string();
+                wait = (wait * 2).min(RECONNECT_MAX_WAIT);
+            }
+        }
+    }
+    false
+}
+
 pub fn hash_available_cameras(// mut cams: ResMut<CaptureDevices>,
     // mut selected: ResMut<SelectedCamera>,
 ) -> (Option<(String, u32)>, HashMap<String, u32>) {
     // this is where the query for cameras should occur and then filter out any repeats
-    let cameras = query(ApiBackend::Auto).unwrap();
+    // machines without any capture devices can still run the test pattern, so this is not fatal
+    let cameras = query(ApiBackend::Auto).unwrap_or_default();
     let mut hash: HashMap<String, _> = HashMap::new();
     for camera in cameras.iter() {
         if hash.contains_key(&camera.human_name()) {
@@ -159,54 +721,137 @@ pub fn hash_available_cameras(// mut cams: ResMut<CaptureDevices>,
     (selected, hash)
 }
 
-// helper function that sets a setting and prints the errors
-pub fn send_camera_setting(cam: &VideoStream, id: KnownCameraControl, value: i64) {
-    if let Err(why) = cam.op_tx.send(CameraSetting {
-        id,
-        control: ControlValueSetter::Integer(value),
-    }) {
+// helper function that sets a setting, keeps track of it for the gui and prints the errors
+pub fn send_camera_setting(
+    cam: &mut VideoStream,
+    id: KnownCameraControl,
+    value: ControlValueSetter,
+) {
+    // the controls differ from camera to camera, so anything this one does not have is skipped
+    if !cam.known_controls.contains_key(&id) {
+        return;
+    }
+    cam.controls.insert(id, value.clone());
+    if let Err(why) = cam
+        .op_tx
+        .send(SourceOp::Control(CameraSetting { id, control: value }))
+    {
         eprintln!("{}", why);
     }
 }
 
-pub fn reset_camera_controls(mut color_settings: ResMut<ColorSettings>, cam: &VideoStream) {
+pub fn reset_camera_controls(mut color_settings: ResMut<ColorSettings>, cam: &mut VideoStream) {
     *color_settings = ColorSettings::default();
+    default_camera_controls(&mut color_settings, cam);
+}
 
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Brightness,
-        color_settings.brightness.into(),
-    );
+pub fn apply_color_settings(color_settings: &ColorSettings, cam: &mut VideoStream) {
+    apply_exposure_settings(color_settings, cam);
+    for (id, value) in color_settings.values() {
+        send_camera_setting(cam, id, ControlValueSetter::Integer(value));
+    }
+}
 
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Contrast,
-        color_settings.contrast.into(),
-    );
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Saturation,
-        color_settings.saturation.into(),
-    );
-    send_camera_setting(cam, KnownCameraControl::Gamma, color_settings.gamma.into());
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Sharpness,
-        color_settings.sharpness.into(),
-    );
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Other(10094861),
-        color_settings.zoom.into(),
-    );
-    send_camera_setting(
-        cam,
-        KnownCameraControl::Other(10094857),
-        color_settings.tilt.into(),
-    );
-    send_camera_setting(
+// the automatic modes are set first, as the camera ignores manual values while they are on
+pub fn apply_exposure_settings(color_settings: &ColorSettings, cam: &mut VideoStream) {
+    let mode = if color_settings.auto_exposure {
+        EXPOSURE_APERTURE_PRIORITY
+    } else {
+        EXPOSURE_MANUAL
+    };
+    send_camera_value(cam, EXPOSURE_AUTO, mode);
+    if !color_settings.auto_exposure {
+        for id in EXPOSURE_CONTROLS {
+            send_camera_value(cam, id, color_settings.exposure);
+        }
+    }
+
+    send_camera_value(
         cam,
-        KnownCameraControl::Other(10094856),
-        color_settings.pan.into(),
+        AUTO_WHITE_BALANCE,
+        color_settings.auto_white_balance as i64,
     );
+    if !color_settings.auto_white_balance {
+        for id in WHITE_BALANCE_CONTROLS {
+            send_camera_value(cam, id, color_settings.white_balance);
+        }
+    }
+
+    for id in GAIN_CONTROLS {
+        send_camera_value(cam, id, color_settings.gain);
+    }
+}
+
+// sends a plain number as whichever kind of value the camera says the control takes
+pub fn send_camera_value(cam: &mut VideoStream, id: KnownCameraControl, value: i64) {
+    let setter = match cam.known_controls.get(&id).map(|c| c.description()) {
+        Some(ControlValueDescription::Boolean { .. }) => ControlValueSetter::Boolean(value != 0),
+        Some(ControlValueDescription::Enum { .. }) => ControlValueSetter::EnumValue(value),
+        _ => ControlValueSetter::Integer(value),
+    };
+    send_camera_setting(cam, id, setter);
+}
+
+/// The range and step of the first of `ids` that the camera has as an integer range.
+pub fn control_range(cam: &VideoStream, ids: &[KnownCameraControl]) -> Option<(i64, i64, i64)> {
+    ids.iter()
+        .find_map(|id| match cam.known_controls.get(id)?.description() {
+            ControlValueDescription::IntegerRange { min, max, step, .. } => {
+                Some((*min, *max, *step))
+            }
+            _ => None,
+        })
+}
+
+// puts every control back to whatever the camera itself reports as the default
+pub fn default_camera_controls(color_settings: &mut ColorSettings, cam: &mut VideoStream) {
+    let defaults: Vec<_> = cam
+        .known_controls
+        .iter()
+        .filter_map(|(id, control)| Some((*id, control_default(control)?)))
+        .collect();
+    for (id, value) in defaults {
+        if let Some(v) = setter_as_integer(&value) {
+            color_settings.set(id, v);
+        }
+        send_camera_setting(cam, id, value);
+    }
+}
+
+pub fn setter_as_integer(value: &ControlValueSetter) -> Option<i64> {
+    match value {
+        ControlValueSetter::Integer(v) | ControlValueSetter::EnumValue(v) => Some(*v),
+        ControlValueSetter::Boolean(b) => Some(*b as i64),
+        _ => None,
+    }
+}
+
+/// The current value of a control, for the kinds of control that the gui can edit.
+pub fn control_value(control: &CameraControl) -> Option<ControlValueSetter> {
+    match control.description() {
+        ControlValueDescription::Integer { value, .. }
+        | ControlValueDescription::IntegerRange { value, .. } => {
+            Some(ControlValueSetter::Integer(*value))
+        }
+        ControlValueDescription::Boolean { value, .. } => Some(ControlValueSetter::Boolean(*value)),
+        ControlValueDescription::Enum { value, .. } => Some(ControlValueSetter::EnumValue(*value)),
+        _ => None,
+    }
+}
+
+/// The value the camera starts up with, for the kinds of control that the gui can edit.
+pub fn control_default(control: &CameraControl) -> Option<ControlValueSetter> {
+    match control.description() {
+        ControlValueDescription::Integer { default, .. }
+        | ControlValueDescription::IntegerRange { default, .. } => {
+            Some(ControlValueSetter::Integer(*default))
+        }
+        ControlValueDescription::Boolean { default, .. } => {
+            Some(ControlValueSetter::Boolean(*default))
+        }
+        ControlValueDescription::Enum { default, .. } => {
+            Some(ControlValueSetter::EnumValue(*default))
+        }
+        _ => None,
+    }
 }
@@ -0,0 +1,475 @@
+// This is for saving stills of what is on the platter, either the camera frame that is being shown or the
+// whole composited output that ends up on the window.
+use std::collections::VecDeque;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_resource::ExtractResource;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, MapMode, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use flume::{unbounded, Receiver, Sender};
+use image::{Rgba, RgbaImage};
+
+use crate::camera::{PixelLayout, VideoStream};
+use crate::material::ShownFrame;
+use crate::record::RecordingFrame;
+use crate::rotation::PlatterPhase;
+use crate::zoetrope::{RotationDirection, Slices, HEADLESS_SIZE};
+
+// kept out of `assets` like the playback folder, so that captures are never embedded into the binary
+pub const CAPTURE_DIR: &str = "./captures";
+
+/// Things on this layer are only drawn to the window and never end up in a capture, like the crosshair.
+pub const WINDOW_ONLY_LAYER: u8 = 1;
+
+/// Renders the same view as the window's camera into an image so that it can be read back.
+#[derive(Component)]
+pub struct OutputCamera;
+
+/// The offscreen copy of the window, shared with the render world so that it knows what to read back.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct OutputReadback {
+    pub target: Option<Handle<Image>>,
+    // only read back while something is waiting on it, as it holds up rendering
+    pub active: bool,
+    sender: Sender<Option<RgbaImage>>,
+}
+
+/// Frames of the composited output read back from the gpu, oldest first. `None` when a frame was asked for
+/// but could not be read, so that the frames still line up with what they were asked for.
+#[derive(Resource)]
+pub struct OutputFrames(pub Receiver<Option<RgbaImage>>);
+
+// what a frame of the composited output is wanted for
+pub enum OutputDestination {
+    File(PathBuf),
+    // the fixed tick of the recording that the frame shows
+    Recording(u64),
+    // kept in `CaptureRequests::latest_output` for whoever asked, like the headless tests
+    Memory,
+}
+
+pub fn output_readback() -> (OutputReadback, OutputFrames) {
+    let (sender, receiver) = unbounded();
+    (
+        OutputReadback {
+            target: None,
+            active: false,
+            sender,
+        },
+        OutputFrames(receiver),
+    )
+}
+
+/// Stills that have been asked for through the gui or the hotkeys, along with every frame of the window
+/// output that is still on its way back from the gpu.
+#[derive(Resource, Default)]
+pub struct CaptureRequests {
+    // also save what is on the window alongside the camera frame
+    pub include_output: bool,
+    snapshot: bool,
+    burst: Option<Burst>,
+    // asked for during this frame, everything here is served by the same read back
+    next_output: Vec<OutputDestination>,
+    // frames that have been read back but not yet received, oldest first
+    waiting_output: VecDeque<Vec<OutputDestination>>,
+    pub last_saved: Option<String>,
+    pub latest_output: Option<RgbaImage>,
+}
+
+// one frame for every slice the platter is turned through, over a single revolution
+struct Burst {
+    dir: PathBuf,
+    slices: u8,
+    taken: u8,
+    // how far the platter has turned forward since the burst started, in slices
+    travelled: f32,
+}
+
+impl CaptureRequests {
+    pub fn snapshot(&mut self) {
+        self.snapshot = true;
+    }
+
+    pub fn start_burst(&mut self, slices: u8) {
+        self.burst = Some(Burst {
+            dir: PathBuf::from(CAPTURE_DIR).join(format!("burst-{}", timestamp())),
+            slices,
+            taken: 0,
+            travelled: 0.,
+        });
+    }
+
+    pub fn cancel_burst(&mut self) {
+        self.burst = None;
+    }
+
+    /// How many frames of the current burst have been taken, out of how many.
+    pub fn burst_progress(&self) -> Option<(u8, u8)> {
+        self.burst.as_ref().map(|burst| (burst.taken, burst.slices))
+    }
+
+    /// Asks for the composited output of the current frame.
+    pub fn request_output(&mut self, destination: OutputDestination) {
+        self.next_output.push(destination);
+    }
+
+    pub fn wants_output(&self) -> bool {
+        !self.next_output.is_empty()
+    }
+
+    fn save(&mut self, image: RgbaImage, path: PathBuf) {
+        self.last_saved = Some(path.display().to_string());
+        save_in_background(image, path);
+    }
+}
+
+// local time down to the millisecond, so that a burst and a snapshot taken together do not collide
+pub fn timestamp() -> String {
+    chrono::Local::now()
+        .format("%Y-%m-%d_%H-%M-%S%.3f")
+        .to_string()
+}
+
+// encoding a 4K png takes long enough to be noticed on the platter, so it is done off the main thread
+fn save_in_background(image: RgbaImage, path: PathBuf) {
+    std::thread::spawn(move || {
+        if let Err(e) = write_png(&image, &path) {
+            eprintln!("Could not save {}: {}", path.display(), e);
+        }
+    });
+}
+
+fn write_png(image: &RgbaImage, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save(path)?;
+    Ok(())
+}
+
+/// The camera frame currently on the zoetrope, converted to rgba if the shader was doing that.
+pub fn current_frame(shown: &ShownFrame) -> Option<RgbaImage> {
+    frame_to_rgba(shown.image.as_ref()?, shown.layout)
+}
+
+pub fn frame_to_rgba(image: &Image, layout: PixelLayout) -> Option<RgbaImage> {
+    let width = image.texture_descriptor.size.width;
+    let height = image.texture_descriptor.size.height;
+    let data = &image.data;
+    match layout {
+        PixelLayout::Rgba => RgbaImage::from_raw(width, height, data.clone()),
+        PixelLayout::Yuyv => {
+            // every texel is a pair of pixels
+            let mut out = RgbaImage::new(width * 2, height);
+            for (i, texel) in data.chunks_exact(4).enumerate() {
+                let (x, y) = ((i as u32 % width) * 2, i as u32 / width);
+                if y >= height {
+                    break;
+                }
+                out.put_pixel(x, y, yuv_to_rgba(texel[0], texel[1], texel[3]));
+                out.put_pixel(x + 1, y, yuv_to_rgba(texel[2], texel[1], texel[3]));
+            }
+            Some(out)
+        }
+        PixelLayout::Nv12 => {
+            let side = width as usize;
+            let chroma = side * side;
+            if data.len() < chroma + side * side / 2 {
+                return None;
+            }
+            Some(RgbaImage::from_fn(width, width, |x, y| {
+                let (x, y) = (x as usize, y as usize);
+                let uv = chroma + (y / 2) * side + x / 2 * 2;
+                yuv_to_rgba(data[y * side + x], data[uv], data[uv + 1])
+            }))
+        }
+    }
+}
+
+// the same BT.601 limited range conversion as the zoetrope's shader
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> Rgba<u8> {
+    let luma = (y as f32 - 16.) * 1.164;
+    let (d, e) = (u as f32 - 128., v as f32 - 128.);
+    let channel = |value: f32| value.round().clamp(0., 255.) as u8;
+    Rgba([
+        channel(luma + 1.596 * e),
+        channel(luma - 0.392 * d - 0.813 * e),
+        channel(luma + 2.017 * d),
+        255,
+    ])
+}
+
+pub fn output_capture_setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut readback: ResMut<OutputReadback>,
+    windows: Query<&Window>,
+) {
+    let (width, height) = windows
+        .get_single()
+        .map_or((HEADLESS_SIZE as u32, HEADLESS_SIZE as u32), |window| {
+            (window.physical_width(), window.physical_height())
+        });
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("output capture"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+    let target = images.add(image);
+
+    commands
+        .spawn(Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(target.clone()),
+                // drawn before the window so that the two never fight over ordering
+                order: -1,
+                is_active: false,
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        })
+        .insert(OutputCamera);
+    readback.target = Some(target);
+}
+
+pub fn output_capture_teardown(
+    mut commands: Commands,
+    query: Query<Entity, With<OutputCamera>>,
+    mut readback: ResMut<OutputReadback>,
+    mut requests: ResMut<CaptureRequests>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    readback.target = None;
+    readback.active = false;
+    requests.next_output.clear();
+    requests.waiting_output.clear();
+    requests.cancel_burst();
+}
+
+// the window's camera can be moved and zoomed from the gui, so the capture follows along
+pub fn output_camera_follow(
+    main: Query<&Transform, (With<VideoStream>, Without<OutputCamera>)>,
+    mut output: Query<&mut Transform, With<OutputCamera>>,
+) {
+    if let (Ok(main), Ok(mut output)) = (main.get_single(), output.get_single_mut()) {
+        *output = *main;
+    }
+}
+
+pub fn capture_hotkeys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut requests: ResMut<CaptureRequests>,
+    slices: Res<Slices>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        if keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift) {
+            requests.start_burst(slices.0);
+        } else {
+            requests.snapshot();
+        }
+    }
+}
+
+pub fn capture_snapshot(mut requests: ResMut<CaptureRequests>, shown: Res<ShownFrame>) {
+    if !requests.snapshot {
+        return;
+    }
+    requests.snapshot = false;
+    let name = format!("snapshot-{}", timestamp());
+    match current_frame(&shown) {
+        Some(frame) => {
+            let path = PathBuf::from(CAPTURE_DIR).join(format!("{}.png", name));
+            requests.save(frame, path);
+        }
+        None => warn!("There is no camera frame to save yet"),
+    }
+    if requests.include_output {
+        let path = PathBuf::from(CAPTURE_DIR).join(format!("{}-output.png", name));
+        requests.request_output(OutputDestination::File(path));
+    }
+}
+
+// runs on the fixed tick right after the platter is turned, taking a frame every time it has gone round
+// by another slice
+pub fn capture_burst(
+    mut requests: ResMut<CaptureRequests>,
+    phase: Res<PlatterPhase>,
+    dir: Res<RotationDirection>,
+    shown: Res<ShownFrame>,
+) {
+    let Some(burst) = requests.burst.as_mut() else {
+        return;
+    };
+    // taken from the crank rather than from where the platter is, so that cranking back, nudging or
+    // snapping never skips ahead. turning back has to be made up before the next frame is taken
+    burst.travelled += dir.animation * phase.last_step();
+    // the first frame is taken straight away, the rest as each slice boundary is passed
+    if burst.travelled + f32::EPSILON < burst.taken as f32 {
+        return;
+    }
+
+    let index = burst.taken;
+    let dir = burst.dir.clone();
+    burst.taken += 1;
+    let done = burst.taken >= burst.slices;
+    if done {
+        requests.burst = None;
+    }
+    if let Some(frame) = current_frame(&shown) {
+        requests.save(frame, dir.join(format!("{:03}.png", index)));
+    }
+    if requests.include_output {
+        requests.request_output(OutputDestination::File(
+            dir.join(format!("{:03}-output.png", index)),
+        ));
+    }
+    if done {
+        info!("Finished burst capture into {}", dir.display());
+    }
+}
+
+// the offscreen camera is only drawn on frames that something has asked for
+pub fn output_capture_demand(
+    mut requests: ResMut<CaptureRequests>,
+    mut readback: ResMut<OutputReadback>,
+    mut cameras: Query<&mut Camera, With<OutputCamera>>,
+) {
+    let active = requests.wants_output();
+    if active {
+        let batch = std::mem::take(&mut requests.next_output);
+        requests.waiting_output.push_back(batch);
+    }
+    if readback.active != active {
+        readback.active = active;
+    }
+    for mut camera in cameras.iter_mut() {
+        if camera.is_active != active {
+            camera.is_active = active;
+        }
+    }
+}
+
+pub fn dispatch_output_frames(
+    mut requests: ResMut<CaptureRequests>,
+    frames: Res<OutputFrames>,
+    mut recorded: EventWriter<RecordingFrame>,
+) {
+    for frame in frames.0.try_iter() {
+        let Some(batch) = requests.waiting_output.pop_front() else {
+            break;
+        };
+        let Some(frame) = frame else {
+            warn!("Lost a frame of the window output");
+            continue;
+        };
+        for destination in batch {
+            match destination {
+                OutputDestination::File(path) => requests.save(frame.clone(), path),
+                OutputDestination::Recording(tick) => recorded.send(RecordingFrame {
+                    tick,
+                    image: frame.clone(),
+                }),
+                OutputDestination::Memory => requests.latest_output = Some(frame.clone()),
+            }
+        }
+    }
+}
+
+/// Runs in the render world after everything has been drawn, copying the offscreen image back to the cpu.
+pub fn readback_output(
+    readback: Res<OutputReadback>,
+    gpu_images: Res<RenderAssets<Image>>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    if !readback.active {
+        return;
+    }
+    let Some(gpu_image) = readback
+        .target
+        .as_ref()
+        .and_then(|target| gpu_images.get(target))
+    else {
+        // the target is not on the gpu yet, but something is still waiting on this frame
+        let _ = readback.sender.send(None);
+        return;
+    };
+    let (width, height) = (gpu_image.size.x as u32, gpu_image.size.y as u32);
+    let row = width as usize * 4;
+    // rows have to be copied out at wgpu's alignment and are trimmed back afterwards
+    let padded_row = RenderDevice::align_copy_bytes_per_row(row);
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("output readback"),
+        size: (padded_row * height as usize) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("output readback"),
+    });
+    encoder.copy_texture_to_buffer(
+        gpu_image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row as u32),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    let (mapped_tx, mapped_rx) = flume::bounded(1);
+    device.map_buffer(&slice, MapMode::Read, move |result| {
+        let _ = mapped_tx.send(result);
+    });
+    // waiting here holds up the next frame, which is only the case while capturing
+    device.poll(wgpu::Maintain::Wait);
+    if !matches!(mapped_rx.recv(), Ok(Ok(()))) {
+        let _ = readback.sender.send(None);
+        return;
+    }
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for padded in slice.get_mapped_range().chunks(padded_row) {
+        pixels.extend_from_slice(&padded[..row]);
+    }
+    buffer.unmap();
+    let _ = readback
+        .sender
+        .send(RgbaImage::from_raw(width, height, pixels));
+}
@@ -0,0 +1,40 @@
+// This is for the small amount of state that is kept on disk between runs of the zoetrope.
+use std::path::PathBuf;
+
+use anyhow::Result;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub const CONFIG_DIR: &str = "./config";
+
+/// Path of a file within [`CONFIG_DIR`].
+pub fn config_path(name: &str) -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join(name)
+}
+
+/// Reads a value that was previously stored with [`save_config`], returning `None` if it has never
+/// been saved or can no longer be read.
+pub fn load_config<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let path = config_path(name);
+    let text = std::fs::read_to_string(&path).ok()?;
+    match ron::from_str(&text) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring unreadable config {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn save_config<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let path = config_path(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(
+        path,
+        ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?,
+    )?;
+    Ok(())
+}
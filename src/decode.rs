@@ -0,0 +1,246 @@
+// This is for turning raw camera buffers into images on a few threads at once, so that grabbing the next
+// frame from the camera is not held up by decoding the last one.
+use std::collections::BTreeMap;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use flume::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use nokhwa::pixel_format::RgbAFormat;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+
+use crate::camera::{DecodeError, Frame, PixelLayout, VideoStream};
+
+// enough to keep up with 4K MJPEG on the mini PC without starving the rest of the app
+const DECODE_WORKERS: usize = 3;
+// how many frames can be waiting on the workers before capturing waits for them
+const MAX_IN_FLIGHT: u64 = DECODE_WORKERS as u64 * 2;
+// longer than any frame takes to decode, a frame still missing after this is given up on
+const DECODE_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct DecodeJob {
+    sequence: u64,
+    buffer: Buffer,
+    crop: (u32, u32, u32),
+    captured_at: Instant,
+}
+
+type Decoded = (u64, Result<Frame, DecodeError>);
+
+/// Decodes frames on a pool of worker threads and hands them back in the order they were captured.
+pub struct DecodePool {
+    job_tx: Option<Sender<DecodeJob>>,
+    done_rx: Receiver<Decoded>,
+    workers: Vec<JoinHandle<()>>,
+    next_job: u64,
+    next_out: u64,
+    // frames that finished ahead of one captured before them
+    finished: BTreeMap<u64, Result<Frame, DecodeError>>,
+}
+
+impl DecodePool {
+    pub fn new() -> Self {
+        let (job_tx, job_rx) = bounded::<DecodeJob>(MAX_IN_FLIGHT as usize);
+        let (done_tx, done_rx) = unbounded::<Decoded>();
+        let workers = (0..DECODE_WORKERS)
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let done_tx = done_tx.clone();
+                // runs until the pool is dropped and the job channel closes
+                std::thread::spawn(move || {
+                    for job in job_rx.iter() {
+                        let sequence = job.sequence;
+                        if done_tx.send((sequence, decode(job))).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Self {
+            job_tx: Some(job_tx),
+            done_rx,
+            workers,
+            next_job: 0,
+            next_out: 0,
+            finished: BTreeMap::new(),
+        }
+    }
+
+    pub fn submit(&mut self, buffer: Buffer, crop: (u32, u32, u32), captured_at: Instant) {
+        let job = DecodeJob {
+            sequence: self.next_job,
+            buffer,
+            crop,
+            captured_at,
+        };
+        if let Some(job_tx) = &self.job_tx {
+            if job_tx.send(job).is_ok() {
+                self.next_job += 1;
+            }
+        }
+    }
+
+    /// The next frame in capture order, waiting for it until `deadline` if it is not decoded yet. Waits
+    /// past that when too many frames are already queued up so that memory does not grow when decoding
+    /// falls behind.
+    pub fn next_decoded(&mut self, deadline: Instant) -> Option<Result<Frame, DecodeError>> {
+        for (sequence, result) in self.done_rx.try_iter() {
+            self.keep(sequence, result);
+        }
+        while self.next_out < self.next_job && !self.finished.contains_key(&self.next_out) {
+            let full = self.next_job - self.next_out >= MAX_IN_FLIGHT;
+            let received = if full {
+                self.done_rx.recv_timeout(DECODE_TIMEOUT)
+            } else {
+                self.done_rx.recv_deadline(deadline)
+            };
+            match received {
+                Ok((sequence, result)) => self.keep(sequence, result),
+                Err(RecvTimeoutError::Timeout) if !full => break,
+                // a worker that panicked never sends its frame back, so it is skipped rather than waited on
+                // for good
+                Err(why) => {
+                    let error = DecodeError(format!(
+                        "Frame {} was never decoded: {}",
+                        self.next_out, why
+                    ));
+                    self.finished.insert(self.next_out, Err(error));
+                }
+            }
+        }
+        let result = self.finished.remove(&self.next_out)?;
+        self.next_out += 1;
+        Some(result)
+    }
+
+    fn keep(&mut self, sequence: u64, result: Result<Frame, DecodeError>) {
+        // anything that turns up after it was given up on has nowhere to go
+        if sequence >= self.next_out {
+            self.finished.insert(sequence, result);
+        }
+    }
+}
+
+impl Default for DecodePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for DecodePool {
+    fn drop(&mut self) {
+        // closing the channel lets the workers finish what they have and stop
+        self.job_tx.take();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                eprintln!("A decode worker panicked");
+            }
+        }
+    }
+}
+
+fn decode(job: DecodeJob) -> Result<Frame, DecodeError> {
+    let start = Instant::now();
+    let (x, y, side) = job.crop;
+    let raw_layout = PixelLayout::of(job.buffer.source_frame_format());
+    // uncompressed formats are cut down first so that only the kept square is converted or uploaded
+    let (image, layout) = match (crop_raw(&job.buffer, job.crop), raw_layout) {
+        // the shader does the conversion for these, so the bytes go up as they are
+        (Some(cropped), PixelLayout::Yuyv | PixelLayout::Nv12) => {
+            (raw_image(cropped, raw_layout), raw_layout)
+        }
+        (Some(cropped), PixelLayout::Rgba) => {
+            let side = cropped.resolution().width();
+            let mut buf = cropped
+                .decode_image::<RgbAFormat>()
+                .map_err(|why| DecodeError(why.to_string()))?;
+            (
+                VideoStream::make_image((0, 0, side), &mut buf),
+                PixelLayout::Rgba,
+            )
+        }
+        (None, _) => {
+            let mut buf = job
+                .buffer
+                .decode_image::<RgbAFormat>()
+                .map_err(|why| DecodeError(why.to_string()))?;
+            (
+                VideoStream::make_image((x, y, side), &mut buf),
+                PixelLayout::Rgba,
+            )
+        }
+    };
+    Ok(Frame {
+        image,
+        layout,
+        captured_at: job.captured_at,
+        decode_time: start.elapsed(),
+    })
+}
+
+// wraps a cropped YUYV or NV12 buffer in a texture the shader knows how to read
+fn raw_image(buffer: Buffer, layout: PixelLayout) -> Image {
+    let side = buffer.resolution().width();
+    let (size, format) = match layout {
+        PixelLayout::Yuyv => ((side / 2, side), TextureFormat::Rgba8Unorm),
+        _ => ((side, side + side / 2), TextureFormat::R8Unorm),
+    };
+    Image::new(
+        Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        buffer.buffer().to_vec(),
+        format,
+    )
+}
+
+// copies the square out of an uncompressed buffer, `None` for compressed formats or a buffer that is
+// smaller than its resolution says. edges are kept even as YUYV and NV12 share color between pixel pairs,
+// and the side is at least a pair so that a tiny crop does not round down to nothing
+fn crop_raw(buffer: &Buffer, (x, y, side): (u32, u32, u32)) -> Option<Buffer> {
+    let width = buffer.resolution().width() as usize;
+    let height = buffer.resolution().height() as usize;
+    let (x, y, side) = (x as usize & !1, y as usize & !1, side.max(2) as usize & !1);
+    let data = buffer.buffer();
+    let format = buffer.source_frame_format();
+    let cropped = match format {
+        FrameFormat::YUYV => copy_rows(data, width * 2, y, side, x * 2, side * 2)?,
+        FrameFormat::GRAY => copy_rows(data, width, y, side, x, side)?,
+        FrameFormat::RAWRGB => copy_rows(data, width * 3, y, side, x * 3, side * 3)?,
+        FrameFormat::NV12 => {
+            // a full size luma plane followed by a half height plane of interleaved chroma
+            let (luma, chroma) = data.split_at(data.len().min(width * height));
+            let mut out = copy_rows(luma, width, y, side, x, side)?;
+            out.extend(copy_rows(chroma, width, y / 2, side / 2, x, side)?);
+            out
+        }
+        _ => return None,
+    };
+    Some(Buffer::new(
+        Resolution::new(side as u32, side as u32),
+        &cropped,
+        format,
+    ))
+}
+
+fn copy_rows(
+    data: &[u8],
+    stride: usize,
+    first_row: usize,
+    rows: usize,
+    start: usize,
+    len: usize,
+) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(rows * len);
+    for row in first_row..first_row + rows {
+        let offset = row * stride + start;
+        out.extend_from_slice(data.get(offset..offset + len)?);
+    }
+    Some(out)
+}
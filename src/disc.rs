@@ -0,0 +1,343 @@
+// This is for turning a burst capture into a phenakistoscope disc that can be printed, cut out and spun at
+// home. The frames go around the disc in the order they were captured, with a viewing slot between each.
+use std::f32::consts::PI;
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use base64::Engine;
+use bevy::prelude::*;
+use flume::Receiver;
+use image::imageops::FilterType;
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+
+use crate::capture::CAPTURE_DIR;
+
+// enough for the frames to survive being printed without the file getting out of hand
+pub const DISC_DPI: f32 = 300.;
+const MARGIN_MM: f32 = 10.;
+const LINE_MM: f32 = 0.3;
+const SPINDLE_MM: f32 = 3.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperSize {
+    A4,
+    A3,
+    Letter,
+    Tabloid,
+}
+
+impl PaperSize {
+    pub const ALL: [PaperSize; 4] = [Self::A4, Self::A3, Self::Letter, Self::Tabloid];
+
+    /// Width and height in millimetres, portrait.
+    pub fn size_mm(&self) -> (f32, f32) {
+        match self {
+            Self::A4 => (210., 297.),
+            Self::A3 => (297., 420.),
+            Self::Letter => (215.9, 279.4),
+            Self::Tabloid => (279.4, 431.8),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::A4 => "A4",
+            Self::A3 => "A3",
+            Self::Letter => "Letter",
+            Self::Tabloid => "Tabloid",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscFormat {
+    Png,
+    Svg,
+}
+
+/// What the disc export in the gui is set to, along with the export that is running.
+#[derive(Resource)]
+pub struct DiscExport {
+    pub paper: PaperSize,
+    pub format: DiscFormat,
+    pub burst: Option<PathBuf>,
+    pub last: Option<String>,
+    running: Option<Receiver<Result<PathBuf, String>>>,
+}
+
+impl Default for DiscExport {
+    fn default() -> Self {
+        Self {
+            paper: PaperSize::A4,
+            format: DiscFormat::Png,
+            burst: None,
+            last: None,
+            running: None,
+        }
+    }
+}
+
+impl DiscExport {
+    // rendering a disc at print resolution takes a few seconds, so it happens on its own thread
+    pub fn start(&mut self) {
+        let Some(burst) = self.burst.clone() else {
+            return;
+        };
+        let (paper, format) = (self.paper, self.format);
+        let (done_tx, done_rx) = flume::bounded(1);
+        std::thread::spawn(move || {
+            let result = export_disc(&burst, paper, format).map_err(|e| e.to_string());
+            let _ = done_tx.send(result);
+        });
+        self.running = Some(done_rx);
+        self.last = Some("Exporting...".to_string());
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Picks up the result of the export once it is done.
+    pub fn poll(&mut self) {
+        let Some(result) = self.running.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.running = None;
+        self.last = Some(match result {
+            Ok(path) => format!("Saved {}", path.display()),
+            Err(e) => format!("Could not export the disc: {}", e),
+        });
+    }
+}
+
+/// Every burst in [`CAPTURE_DIR`], newest first.
+pub fn available_bursts() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = std::fs::read_dir(CAPTURE_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.is_dir()
+                        && path
+                            .file_name()
+                            .is_some_and(|name| name.to_string_lossy().starts_with("burst-"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // the names start with a timestamp, so they sort by age
+    found.sort();
+    found.reverse();
+    found
+}
+
+pub fn export_disc(burst: &Path, paper: PaperSize, format: DiscFormat) -> Result<PathBuf> {
+    let frames = load_burst(burst)?;
+    let layout = DiscLayout::new(paper, frames.len());
+    let name = format!("disc-{}", paper.name());
+    let path = match format {
+        DiscFormat::Png => {
+            let path = burst.join(format!("{}.png", name));
+            render_png(&frames, &layout).save(&path)?;
+            path
+        }
+        DiscFormat::Svg => {
+            let path = burst.join(format!("{}.svg", name));
+            std::fs::write(&path, render_svg(&frames, &layout)?)?;
+            path
+        }
+    };
+    Ok(path)
+}
+
+// the camera frames of a burst, in the order they were taken. the window output saved alongside them is
+// left out
+fn load_burst(dir: &Path) -> Result<Vec<RgbaImage>> {
+    let mut files: Vec<(u32, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            if path.extension()?.to_str()? != "png" {
+                return None;
+            }
+            let number = path.file_stem()?.to_str()?.parse().ok()?;
+            Some((number, path))
+        })
+        .collect();
+    files.sort();
+    if files.len() < 2 {
+        bail!("{} needs at least two frames", dir.display());
+    }
+    files
+        .into_iter()
+        .map(|(_, path)| Ok(image::open(path)?.to_rgba8()))
+        .collect()
+}
+
+// every measurement is in millimetres from the center of the disc
+struct DiscLayout {
+    paper: (f32, f32),
+    frames: usize,
+    radius: f32,
+    // the slots are cut from here out to the edge
+    slot_inner: f32,
+    slot_width: f32,
+    // where the center of each frame sits
+    frame_radius: f32,
+    frame_side: f32,
+}
+
+impl DiscLayout {
+    fn new(paper: PaperSize, frames: usize) -> Self {
+        let (width, height) = paper.size_mm();
+        let radius = (width.min(height) - 2. * MARGIN_MM) / 2.;
+        let step = 2. * PI / frames as f32;
+        // the frames sit inside 80% of the disc, spaced so that neighbours do not quite touch
+        let spacing = (step / 2.).sin() * 0.95;
+        let frame_side = 1.6 * radius * spacing / (1. + spacing);
+        Self {
+            paper: (width, height),
+            frames,
+            radius,
+            slot_inner: radius * 0.84,
+            slot_width: (radius * step * 0.15).clamp(1.5, 4.),
+            frame_radius: 0.8 * radius - frame_side / 2.,
+            frame_side,
+        }
+    }
+
+    fn step(&self) -> f32 {
+        2. * PI / self.frames as f32
+    }
+
+    // clockwise from the top of the page, frames on whole steps and slots half way between them
+    fn frame_angle(&self, index: usize) -> f32 {
+        index as f32 * self.step()
+    }
+
+    fn slot_angle(&self, index: usize) -> f32 {
+        (index as f32 + 0.5) * self.step()
+    }
+}
+
+fn mm_to_px(mm: f32) -> f32 {
+    mm * DISC_DPI / 25.4
+}
+
+fn render_png(frames: &[RgbaImage], layout: &DiscLayout) -> RgbaImage {
+    let side = mm_to_px(layout.frame_side).round().max(1.) as u32;
+    let frames: Vec<RgbaImage> = frames
+        .iter()
+        .map(|frame| image::imageops::resize(frame, side, side, FilterType::Triangle))
+        .collect();
+    let width = mm_to_px(layout.paper.0).round() as u32;
+    let height = mm_to_px(layout.paper.1).round() as u32;
+    let (cx, cy) = (width as f32 / 2., height as f32 / 2.);
+    let px_to_mm = 25.4 / DISC_DPI;
+    let step = layout.step();
+    let black = Rgba([0, 0, 0, 255]);
+    let white = Rgba([255, 255, 255, 255]);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = ((x as f32 - cx) * px_to_mm, (y as f32 - cy) * px_to_mm);
+        let distance = (dx * dx + dy * dy).sqrt();
+        // the cutting lines, around the edge and the spindle hole
+        if (distance - layout.radius).abs() < LINE_MM / 2.
+            || (distance - SPINDLE_MM).abs() < LINE_MM / 2.
+        {
+            return black;
+        }
+        if distance > layout.radius {
+            return white;
+        }
+        let angle = dx.atan2(-dy).rem_euclid(2. * PI);
+
+        if distance >= layout.slot_inner {
+            let slot = (angle / step - 0.5)
+                .round()
+                .rem_euclid(layout.frames as f32) as usize;
+            let mut offset = angle - layout.slot_angle(slot);
+            offset = (offset + PI).rem_euclid(2. * PI) - PI;
+            if (distance * offset).abs() < layout.slot_width / 2. {
+                return black;
+            }
+        }
+
+        let index = (angle / step).round() as usize % layout.frames;
+        let theta = layout.frame_angle(index);
+        // into the frame's own coordinates, with its top facing out from the center
+        let along = dx * theta.cos() + dy * theta.sin();
+        let out = dx * theta.sin() - dy * theta.cos() - layout.frame_radius;
+        let half = layout.frame_side / 2.;
+        if along * along + out * out > half * half {
+            return white;
+        }
+        let fx = ((along / layout.frame_side + 0.5) * side as f32) as u32;
+        let fy = ((0.5 - out / layout.frame_side) * side as f32) as u32;
+        *frames[index].get_pixel(fx.min(side - 1), fy.min(side - 1))
+    })
+}
+
+fn render_svg(frames: &[RgbaImage], layout: &DiscLayout) -> Result<String> {
+    let (width, height) = layout.paper;
+    let (cx, cy) = (width / 2., height / 2.);
+    let side = mm_to_px(layout.frame_side).round().max(1.) as u32;
+    let half = layout.frame_side / 2.;
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    )?;
+    writeln!(
+        svg,
+        r#"<defs><clipPath id="frame"><circle cx="0" cy="0" r="{}"/></clipPath></defs>"#,
+        half
+    )?;
+    writeln!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    )?;
+
+    for (index, frame) in frames.iter().enumerate() {
+        let theta = layout.frame_angle(index);
+        let resized = image::imageops::resize(frame, side, side, FilterType::Triangle);
+        let mut png = Vec::new();
+        resized.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        writeln!(
+            svg,
+            r#"<g transform="translate({x} {y}) rotate({deg})"><image x="{o}" y="{o}" width="{s}" height="{s}" clip-path="url(#frame)" href="data:image/png;base64,{data}"/></g>"#,
+            x = cx + layout.frame_radius * theta.sin(),
+            y = cy - layout.frame_radius * theta.cos(),
+            deg = theta.to_degrees(),
+            o = -half,
+            s = layout.frame_side,
+            data = base64::engine::general_purpose::STANDARD.encode(&png)
+        )?;
+    }
+    for index in 0..layout.frames {
+        writeln!(
+            svg,
+            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" fill="black" transform="translate({cx} {cy}) rotate({deg})"/>"#,
+            x = -layout.slot_width / 2.,
+            y = -layout.radius,
+            w = layout.slot_width,
+            h = layout.radius - layout.slot_inner,
+            cx = cx,
+            cy = cy,
+            deg = layout.slot_angle(index).to_degrees()
+        )?;
+    }
+    for radius in [layout.radius, SPINDLE_MM] {
+        writeln!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="black" stroke-width="{}"/>"#,
+            cx, cy, radius, LINE_MM
+        )?;
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
@@ -1,14 +1,33 @@
+use std::path::PathBuf;
+
 use crate::{
-    audio::VolumeEvent,
-    camera::{reset_camera_controls, send_camera_setting, ColorSettings, VideoStream},
+    audio::{PlayingSong, VolumeEvent},
+    camera::{
+        apply_color_settings, apply_exposure_settings, control_range, default_camera_controls,
+        save_crop, send_camera_setting, setter_as_integer, ColorSettings, CropRegion, FormatNotice,
+        SourceStatus, VideoStream, AUTO_WHITE_BALANCE, EXPOSURE_AUTO, EXPOSURE_CONTROLS,
+        GAIN_CONTROLS, WHITE_BALANCE_CONTROLS,
+    },
+    capture::{CaptureRequests, OutputCamera},
+    disc::{available_bursts, DiscExport, DiscFormat, PaperSize},
+    overlay::{OverlayFollow, SliceOverlay},
+    profile::{CameraProfiles, Profile, ProfileName, SavedFormat},
+    record::{start_recording, Recording, RecordingFormat},
+    rotation::{slice_angle, PlatterPhase, ResponseCurve},
+    setup::{RunningStates, Settings},
+    strobe::Strobe,
     zoetrope::{
-        Direction, RotationDirection, ZoetropeAnimationThresholdSpeed, ZoetropeImage, TOP_BAR_SIZE,
+        Direction, Preset, RotationDirection, Slices, TickMode, ZoetropeAnimationThresholdSpeed,
+        ZoetropeImage,
     },
 };
 use bevy::{prelude::*, sprite::Mesh2dHandle};
 use bevy_egui::{egui, EguiContexts};
+use bevy_kira_audio::{AudioInstance, AudioSource};
 
-use nokhwa::utils::KnownCameraControl;
+use nokhwa::utils::{
+    CameraControl, ControlValueDescription, ControlValueSetter, KnownCameraControl,
+};
 
 #[derive(Resource, Default)]
 pub struct CameraCrosshair(pub bool);
@@ -36,150 +55,166 @@ pub fn gui_full(
     mut color_settings: ResMut<ColorSettings>,
     mut vol_event: EventWriter<VolumeEvent>,
     mut vol: ResMut<Volume>,
-    mut query: Query<&mut Transform, With<Camera>>,
+    mut query: Query<&mut Transform, (With<Camera>, Without<OutputCamera>)>,
     window_query: Query<&Window>,
-    mut threshold: ResMut<ZoetropeAnimationThresholdSpeed>,
-    cam_query: Query<&VideoStream>,
+    mut cam_query: Query<&mut VideoStream>,
     mut circle: Query<&mut Mesh2dHandle, With<ZoetropeImage>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut directions: ResMut<RotationDirection>,
+    mut profiles: ResMut<CameraProfiles>,
+    mut profile_name: ResMut<ProfileName>,
+    mut format_notice: ResMut<FormatNotice>,
+    mut next_state: ResMut<NextState<RunningStates>>,
 ) {
     let window = window_query.single();
     let mut transform = query.single_mut();
-    let cam = cam_query.single();
+    let mut cam = cam_query.single_mut();
     egui::Window::new("Effects")
         .vscroll(true)
         .open(&mut ui_state.is_window_open)
         .show(ctx.ctx_mut(), |ui| {
-            // Brightness
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.brightness, -15..=15)
-                        .text("Brightness")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Brightness,
-                    color_settings.brightness.into(),
-                );
+            // exposure, white balance and gain depend on each other, so they are handled together
+            let has = |ids: &[KnownCameraControl]| {
+                ids.iter().any(|id| cam.known_controls.contains_key(id))
+            };
+            let mut exposure_changed = false;
+            if has(&[EXPOSURE_AUTO]) {
+                exposure_changed |= ui
+                    .checkbox(&mut color_settings.auto_exposure, "Auto Exposure")
+                    .changed();
             }
-
-            // Contrast
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.contrast, 0..=30)
-                        .text("Contrast")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Contrast,
-                    color_settings.contrast.into(),
-                );
+            if let Some((min, max, step)) = control_range(&cam, &EXPOSURE_CONTROLS) {
+                exposure_changed |= ui
+                    .add_enabled(
+                        !color_settings.auto_exposure,
+                        egui::Slider::new(&mut color_settings.exposure, min..=max)
+                            .step_by(step.max(1) as f64)
+                            .text("Exposure Time (100us)")
+                            .show_value(true),
+                    )
+                    .changed();
             }
-
-            // Saturation
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.saturation, 0..=60)
-                        .text("Saturation")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Saturation,
-                    color_settings.saturation.into(),
-                );
+            if has(&[AUTO_WHITE_BALANCE]) {
+                exposure_changed |= ui
+                    .checkbox(&mut color_settings.auto_white_balance, "Auto White Balance")
+                    .changed();
             }
-
-            // Gamma
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.gamma, 40..=500)
-                        .text("Gamma")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(cam, KnownCameraControl::Gamma, color_settings.gamma.into());
+            if let Some((min, max, step)) = control_range(&cam, &WHITE_BALANCE_CONTROLS) {
+                exposure_changed |= ui
+                    .add_enabled(
+                        !color_settings.auto_white_balance,
+                        egui::Slider::new(&mut color_settings.white_balance, min..=max)
+                            .step_by(step.max(1) as f64)
+                            .text("White Balance (K)")
+                            .show_value(true),
+                    )
+                    .changed();
             }
-
-            // Sharpness
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.sharpness, 0..=127)
-                        .text("Sharpness")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Sharpness,
-                    color_settings.sharpness.into(),
-                );
+            if let Some((min, max, step)) = control_range(&cam, &GAIN_CONTROLS) {
+                exposure_changed |= ui
+                    .add(
+                        egui::Slider::new(&mut color_settings.gain, min..=max)
+                            .step_by(step.max(1) as f64)
+                            .text("Gain")
+                            .show_value(true),
+                    )
+                    .changed();
             }
-
-            // Zoom
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.zoom, 100..=800)
-                        .drag_value_speed(0.4)
-                        .text("Zoom")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Other(10094861),
-                    color_settings.zoom.into(),
-                );
+            if exposure_changed {
+                apply_exposure_settings(&color_settings, &mut cam);
             }
+            let managed = |id: &KnownCameraControl| {
+                [EXPOSURE_AUTO, AUTO_WHITE_BALANCE].contains(id)
+                    || EXPOSURE_CONTROLS.contains(id)
+                    || WHITE_BALANCE_CONTROLS.contains(id)
+                    || GAIN_CONTROLS.contains(id)
+            };
+            ui.separator();
 
-            // Tilt
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.tilt, -648000..=648000)
-                        .drag_value_speed(400.0)
-                        .text("Tilt")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Other(10094857),
-                    color_settings.tilt.into(),
-                );
+            // one widget for every other control the camera reports, using its own ranges
+            let mut changed = Vec::new();
+            for (id, control) in cam.known_controls.iter().filter(|(id, _)| !managed(*id)) {
+                if let Some(value) = cam.controls.get(id) {
+                    let mut value = value.clone();
+                    if control_widget(ui, control, &mut value) {
+                        changed.push((*id, value));
+                    }
+                }
             }
-
-            // Pan
-            if ui
-                .add(
-                    egui::Slider::new(&mut color_settings.pan, -648000..=648000)
-                        .drag_value_speed(400.0)
-                        .text("Pan")
-                        .show_value(true),
-                )
-                .changed()
-            {
-                send_camera_setting(
-                    cam,
-                    KnownCameraControl::Other(10094856),
-                    color_settings.pan.into(),
-                );
+            if cam.known_controls.is_empty() {
+                ui.label("This source does not have any controls");
+            }
+            for (id, value) in changed {
+                if let Some(v) = setter_as_integer(&value) {
+                    color_settings.set(id, v);
+                }
+                send_camera_setting(&mut cam, id, value);
             }
 
             if ui.add(egui::Button::new("Reset to Defaults")).clicked() {
-                reset_camera_controls(color_settings, cam);
+                default_camera_controls(&mut color_settings, &mut cam);
+            }
+
+            // named sets of the above, saved to disk for this camera
+            ui.separator();
+            ui.label("Profiles");
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut profile_name.0);
+                if ui
+                    .add_enabled(!profile_name.0.is_empty(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    profiles.profiles.insert(
+                        profile_name.0.clone(),
+                        Profile {
+                            color: color_settings.clone(),
+                            crop: cam.format.map(|_| cam.crop),
+                            format: cam.format.map(SavedFormat::from),
+                        },
+                    );
+                    if let Err(e) = profiles.save() {
+                        warn!("Could not save the profile: {}", e);
+                    }
+                }
+            });
+            let mut load = None;
+            let mut make_default = None;
+            for name in profiles.profiles.keys() {
+                ui.horizontal(|ui| {
+                    if profiles.default.as_ref() == Some(name) {
+                        ui.label(format!("{} (default)", name));
+                    } else {
+                        ui.label(name);
+                    }
+                    if ui.add(egui::Button::new("Load")).clicked() {
+                        load = Some(name.clone());
+                    }
+                    if ui.add(egui::Button::new("Set Default")).clicked() {
+                        make_default = Some(name.clone());
+                    }
+                });
+            }
+            if let Some(name) = load {
+                if let Some(profile) = profiles.profiles.get(&name).cloned() {
+                    *color_settings = profile.color;
+                    apply_color_settings(&color_settings, &mut cam);
+                    profile.apply_crop(&profiles.camera, &mut cam);
+                    // the format can only change when the camera is opened again
+                    if profile.format.is_some()
+                        && profile.format != cam.format.map(SavedFormat::from)
+                    {
+                        format_notice.0 = Some(format!(
+                            "{} was saved with a different format, set it as the default to use it the next time the camera is opened",
+                            name
+                        ));
+                    }
+                }
+            }
+            if make_default.is_some() {
+                profiles.default = make_default;
+                if let Err(e) = profiles.save() {
+                    warn!("Could not save the default profile: {}", e);
+                }
             }
         });
 
@@ -222,35 +257,182 @@ pub fn gui_full(
     egui::Window::new("Presets")
         .open(&mut ui_state.is_window_open)
         .show(ctx.ctx_mut(), |ui| {
-            if ui.add(egui::Button::new("Re-Center")).clicked() {
-                let size = (window.height() / 2.).ceil() + TOP_BAR_SIZE as f32;
-                *circle.single_mut() = meshes.add(shape::Circle::new(size).into()).into();
-                *transform = Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y);
-            }
-            if ui.add(egui::Button::new("Semi-Circle")).clicked() {
-                let size = ((window.width() / 2.0) * 0.99).ceil();
-                *circle.single_mut() = meshes.add(shape::Circle::new(size).into()).into();
-                *transform = Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y);
-                transform.translation.y = window.resolution.height() / 2.0;
-            }
-            if ui.add(egui::Button::new("Right")).clicked() {
-                let size = (window.height()).ceil();
-                *circle.single_mut() = meshes.add(shape::Circle::new(size).into()).into();
-                let location = ((window.width()) / 2.0).ceil();
-                *transform = Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y);
-                transform.translation.y = window.resolution.height() / 2.0;
-                transform.translation.x = -location;
-            }
-            if ui.add(egui::Button::new("Left")).clicked() {
-                let size = (window.height()).ceil();
-                *circle.single_mut() = meshes.add(shape::Circle::new(size).into()).into();
-                let location = ((window.width()) / 2.0).ceil();
-                *transform = Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y);
-                transform.translation.y = window.resolution.height() / 2.0;
-                transform.translation.x = location;
+            for preset in Preset::ALL {
+                if ui.add(egui::Button::new(preset.name())).clicked() {
+                    let (size, layout) = preset.layout(window.width(), window.height());
+                    *circle.single_mut() = meshes.add(shape::Circle::new(size).into()).into();
+                    *transform = layout;
+                }
+            }
+            if ui.add(egui::Button::new("Back to Setup")).clicked() {
+                next_state.set(RunningStates::Setup);
+            }
+        });
+}
+
+pub fn gui_animation(
+    mut ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut slices: ResMut<Slices>,
+    mut mode: ResMut<TickMode>,
+    fixed_time: Res<FixedTime>,
+    mut strobe: ResMut<Strobe>,
+    mut overlay: ResMut<SliceOverlay>,
+    recording: Res<Recording>,
+) {
+    egui::Window::new("Animation")
+        .open(&mut ui_state.is_window_open)
+        .show(ctx.ctx_mut(), |ui| {
+            // a recording is written at the tick rate it started with, so the tick is left alone until
+            // it is stopped
+            let locked = recording.is_recording();
+            if locked {
+                ui.label("The slices and tick can't be changed while recording");
+            }
+            // only written back when it changes, so that the tick is not recomputed every frame
+            let mut count = slices.0;
+            ui.add_enabled_ui(!locked, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut count).clamp_range(1..=u8::MAX));
+                    ui.label("Slices");
+                });
+            });
+            if count != slices.0 {
+                slices.0 = count;
+            }
+
+            ui.separator();
+            ui.label("Turn the platter by a slice");
+            let mut picked = *mode;
+            ui.add_enabled_ui(!locked, |ui| {
+                ui.radio_value(
+                    &mut picked,
+                    TickMode::PerSlice,
+                    "Every 1/slices of a second",
+                );
+                ui.radio_value(
+                    &mut picked,
+                    TickMode::PerFrame,
+                    "Every displayed frame (vsync)",
+                );
+                let hz = match picked {
+                    TickMode::Fixed(hz) => hz,
+                    _ => 60.,
+                };
+                ui.horizontal(|ui| {
+                    if ui
+                        .radio(matches!(picked, TickMode::Fixed(_)), "At a fixed rate")
+                        .clicked()
+                    {
+                        picked = TickMode::Fixed(hz);
+                    }
+                    let mut rate = hz;
+                    if ui
+                        .add_enabled(
+                            matches!(picked, TickMode::Fixed(_)),
+                            egui::Slider::new(&mut rate, 1.0..=240.0).suffix(" Hz"),
+                        )
+                        .changed()
+                    {
+                        picked = TickMode::Fixed(rate);
+                    }
+                });
+            });
+            // like the slices, only written back when it changes
+            if picked != *mode {
+                *mode = picked;
+            }
+            ui.label(format!(
+                "Ticking at {:.1} Hz",
+                1. / fixed_time.period.as_secs_f32()
+            ));
+
+            ui.separator();
+            // every step is a single frame, so there is nothing in between them to blank
+            let per_frame = *mode == TickMode::PerFrame;
+            ui.add_enabled_ui(!per_frame, |ui| {
+                ui.checkbox(&mut strobe.enabled, "Strobe");
+                ui.add_enabled(
+                    strobe.enabled,
+                    egui::Slider::new(&mut strobe.duty, 0.05..=1.0)
+                        .text("Shown for this much of each step")
+                        .show_value(true),
+                );
+                ui.add_enabled(
+                    strobe.enabled,
+                    egui::Slider::new(&mut strobe.phase, 0.0..=1.0)
+                        .text("Phase")
+                        .show_value(true),
+                );
+            });
+            if per_frame {
+                ui.label("The strobe needs more than one frame to a step");
+            }
+
+            ui.separator();
+            ui.checkbox(&mut overlay.enabled, "Slice Guides");
+            ui.add_enabled_ui(overlay.enabled, |ui| {
+                ui.radio_value(
+                    &mut overlay.follow,
+                    OverlayFollow::Platter,
+                    "Turn with the platter",
+                );
+                ui.radio_value(&mut overlay.follow, OverlayFollow::Camera, "Stay still");
+                ui.add(
+                    egui::Slider::new(&mut overlay.rings, 0..=8)
+                        .text("Guide rings")
+                        .show_value(true),
+                );
+            });
+        });
+}
+
+// for lining the platter up with the sculpture: turn the crank until a figure is where slice 0 should be,
+// set the reference there, then nudge it the rest of the way
+pub fn gui_calibration(
+    mut ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    slices: Res<Slices>,
+    mut phase: ResMut<PlatterPhase>,
+) {
+    egui::Window::new("Calibration")
+        .open(&mut ui_state.is_window_open)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.label(format!(
+                "The reference is at slice {}, offset by {:.1}°",
+                phase.slice(slices.0),
+                phase.offset.to_degrees()
+            ));
+            if ui.button("Set Reference Here").clicked() {
+                phase.set_reference(slices.0);
+            }
+            ui.horizontal(|ui| {
+                ui.label("Nudge");
+                for degrees in [-1., -0.1, 0.1, 1.] {
+                    if ui.button(format!("{:+}°", degrees)).clicked() {
+                        phase.nudge(f32::to_radians(degrees));
+                    }
+                }
+                if ui.button("-½ slice").clicked() {
+                    phase.nudge(-slice_angle(slices.0) / 2.);
+                }
+                if ui.button("+½ slice").clicked() {
+                    phase.nudge(slice_angle(slices.0) / 2.);
+                }
+            });
+            ui.checkbox(&mut phase.snap, "Snap to whole slices");
+            if ui.button("Reset").clicked() {
+                phase.reset();
             }
         });
+}
 
+pub fn gui_rotation_speed(
+    mut ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut threshold: ResMut<ZoetropeAnimationThresholdSpeed>,
+    mut curve: ResMut<ResponseCurve>,
+) {
     egui::Window::new("Rotational Speed Threshold")
         .open(&mut ui_state.is_window_open)
         .show(ctx.ctx_mut(), |ui| {
@@ -259,9 +441,412 @@ pub fn gui_full(
                     .text("Required Rotational Speed to animate fully")
                     .show_value(true),
             );
+            ui.separator();
+            egui::ComboBox::from_label("Response Curve")
+                .selected_text(curve.name())
+                .show_ui(ui, |ui| {
+                    for option in ResponseCurve::defaults() {
+                        let name = option.name();
+                        // picking the kind that is already selected keeps its settings
+                        if ui.selectable_label(curve.name() == name, name).clicked()
+                            && curve.name() != name
+                        {
+                            *curve = option;
+                        }
+                    }
+                });
+            match &mut *curve {
+                ResponseCurve::Exponential(power) => {
+                    ui.add(
+                        egui::Slider::new(power, 0.25..=4.)
+                            .text("Power")
+                            .show_value(true),
+                    );
+                }
+                ResponseCurve::DeadZone(width) => {
+                    ui.add(
+                        egui::Slider::new(width, 0.0..=0.9)
+                            .text("Ignored below this fraction of full speed")
+                            .show_value(true),
+                    );
+                }
+                ResponseCurve::Piecewise(points) => {
+                    let mut remove = None;
+                    for (i, (x, y)) in points.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Slider::new(x, 0.01..=0.99).text("Crank"));
+                            ui.add(egui::Slider::new(y, 0.0..=1.).text("Speed"));
+                            if ui.button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove {
+                        points.remove(i);
+                    }
+                    if ui.button("Add Point").clicked() {
+                        points.push((0.5, 0.5));
+                    }
+                }
+                ResponseCurve::Linear | ResponseCurve::SCurve => {}
+            }
+            curve_preview(ui, &curve);
         });
 }
 
+// a small plot of the curve from a still crank to full speed
+fn curve_preview(ui: &mut egui::Ui, curve: &ResponseCurve) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(160., 160.), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_stroke(rect, 0., egui::Stroke::new(1., egui::Color32::GRAY));
+    let points: Vec<egui::Pos2> = (0..=64)
+        .map(|i| {
+            let x = i as f32 / 64.;
+            egui::pos2(
+                rect.left() + x * rect.width(),
+                rect.bottom() - curve.apply(x) * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(2., egui::Color32::WHITE),
+    ));
+}
+
+// draws the widget that suits the kind of control, returning whether it was changed
+fn control_widget(
+    ui: &mut egui::Ui,
+    control: &CameraControl,
+    value: &mut ControlValueSetter,
+) -> bool {
+    match (control.description(), value) {
+        (
+            ControlValueDescription::IntegerRange { min, max, step, .. },
+            ControlValueSetter::Integer(v),
+        ) => ui
+            .add(
+                egui::Slider::new(v, *min..=*max)
+                    .step_by((*step).max(1) as f64)
+                    .text(control.name())
+                    .show_value(true),
+            )
+            .changed(),
+        (ControlValueDescription::Integer { step, .. }, ControlValueSetter::Integer(v)) => {
+            ui.horizontal(|ui| {
+                let changed = ui
+                    .add(egui::DragValue::new(v).speed((*step).max(1) as f64))
+                    .changed();
+                ui.label(control.name());
+                changed
+            })
+            .inner
+        }
+        (ControlValueDescription::Boolean { .. }, ControlValueSetter::Boolean(v)) => {
+            ui.checkbox(v, control.name()).changed()
+        }
+        (ControlValueDescription::Enum { possible, .. }, ControlValueSetter::EnumValue(v)) => {
+            let mut changed = false;
+            egui::ComboBox::from_label(control.name())
+                .selected_text(v.to_string())
+                .show_ui(ui, |ui| {
+                    for option in possible.iter() {
+                        changed |= ui
+                            .selectable_value(v, *option, option.to_string())
+                            .changed();
+                    }
+                });
+            changed
+        }
+        // strings, points and the like are not something a camera for this is expected to have
+        _ => false,
+    }
+}
+
+pub fn gui_crop(
+    mut ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut cam_query: Query<&mut VideoStream>,
+    settings: Res<Settings>,
+) {
+    let mut cam = cam_query.single_mut();
+    // only real cameras have a frame to crop out of
+    let Some(format) = cam.format else {
+        return;
+    };
+    let (width, height) = (format.width(), format.height());
+    egui::Window::new("Crop")
+        .open(&mut ui_state.is_window_open)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Camera format: {}x{} at {} fps",
+                width,
+                height,
+                format.frame_rate()
+            ));
+            let mut crop = cam.crop;
+            // the crop follows the sliders as they move, but is only saved once they are let go of
+            let mut changed = false;
+            let mut finished = false;
+            let mut track = |response: egui::Response| {
+                changed |= response.changed();
+                finished |= response.drag_released() || response.lost_focus();
+            };
+            track(
+                ui.add(
+                    egui::Slider::new(&mut crop.size, 1..=width.min(height))
+                        .text("Size")
+                        .show_value(true),
+                ),
+            );
+            let max_x = (width - crop.size.min(width)) as i32 / 2;
+            let max_y = (height - crop.size.min(height)) as i32 / 2;
+            track(
+                ui.add(
+                    egui::Slider::new(&mut crop.offset.0, -max_x..=max_x)
+                        .text("Horizontal Offset")
+                        .show_value(true),
+                ),
+            );
+            track(
+                ui.add(
+                    egui::Slider::new(&mut crop.offset.1, -max_y..=max_y)
+                        .text("Vertical Offset")
+                        .show_value(true),
+                ),
+            );
+            if ui.add(egui::Button::new("Center")).clicked() {
+                crop = CropRegion::centered(format.resolution());
+                changed = true;
+                finished = true;
+            }
+            if changed {
+                cam.set_crop(crop);
+            }
+            if finished {
+                if let Some(source) = &settings.source {
+                    if let Err(e) = save_crop(&source.name(), cam.crop) {
+                        warn!("Could not save the crop: {}", e);
+                    }
+                }
+            }
+        });
+}
+
+pub fn gui_capture(
+    mut ctx: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut requests: ResMut<CaptureRequests>,
+    mut recording: ResMut<Recording>,
+    slices: Res<Slices>,
+    fixed_time: Res<FixedTime>,
+    song: Option<Res<PlayingSong>>,
+    sources: Option<Res<Assets<AudioSource>>>,
+    instances: Option<Res<Assets<AudioInstance>>>,
+    mode: Res<TickMode>,
+    mut disc: ResMut<DiscExport>,
+) {
+    disc.poll();
+    egui::Window::new("Capture")
+        .open(&mut ui_state.is_window_open)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.checkbox(&mut requests.include_output, "Include Window Output");
+            ui.horizontal(|ui| {
+                if ui.add(egui::Button::new("Snapshot")).clicked() {
+                    requests.snapshot();
+                }
+                match requests.burst_progress() {
+                    Some((taken, total)) => {
+                        ui.label(format!("Burst {}/{}", taken, total));
+                        if ui.add(egui::Button::new("Cancel Burst")).clicked() {
+                            requests.cancel_burst();
+                        }
+                    }
+                    None => {
+                        if ui.add(egui::Button::new("Burst")).clicked() {
+                            requests.start_burst(slices.0);
+                        }
+                    }
+                }
+            });
+            ui.label("F12 takes a snapshot, Shift+F12 a burst of one frame per slice");
+            if let Some(path) = &requests.last_saved {
+                ui.label(format!("Saved {}", path));
+            }
+
+            ui.separator();
+            ui.heading("Recording");
+            ui.add_enabled_ui(!recording.is_recording(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(
+                        &mut recording.format,
+                        RecordingFormat::PngSequence,
+                        "PNG Sequence",
+                    );
+                    ui.radio_value(
+                        &mut recording.format,
+                        RecordingFormat::MjpegAvi,
+                        "MJPEG AVI",
+                    );
+                });
+            });
+            match (recording.ticks(), recording.elapsed()) {
+                (Some(ticks), Some(elapsed)) => {
+                    ui.label(format!(
+                        "Recording {} ticks ({:.1}s)",
+                        ticks,
+                        elapsed.as_secs_f32()
+                    ));
+                    if ui.add(egui::Button::new("Stop Recording")).clicked() {
+                        recording.stop();
+                    }
+                }
+                _ => {
+                    // every frame of a recording lasts as long as a tick, which keeps changing per frame
+                    let per_frame = *mode == TickMode::PerFrame;
+                    if per_frame {
+                        ui.label("Recording needs the platter to turn at a steady rate");
+                    }
+                    if ui
+                        .add_enabled(!per_frame, egui::Button::new("Start Recording"))
+                        .clicked()
+                    {
+                        // without a song playing the recording is left silent
+                        let soundtrack = match (song, sources, instances) {
+                            (Some(song), Some(sources), Some(instances)) => {
+                                song.soundtrack(&sources, &instances)
+                            }
+                            _ => None,
+                        };
+                        start_recording(&mut recording, &fixed_time, soundtrack);
+                    }
+                }
+            }
+            if recording.is_finishing() {
+                ui.label("Writing out the recording...");
+            } else if let Some(path) = &recording.last_saved {
+                ui.label(format!("Recorded {}", path));
+            }
+
+            ui.separator();
+            ui.heading("Printable Disc");
+            let name = |path: &PathBuf| {
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            };
+            egui::ComboBox::from_label("Burst")
+                .selected_text(disc.burst.as_ref().map_or("None".to_string(), name))
+                .show_ui(ui, |ui| {
+                    for burst in available_bursts() {
+                        let label = name(&burst);
+                        ui.selectable_value(&mut disc.burst, Some(burst), label);
+                    }
+                });
+            ui.horizontal(|ui| {
+                for paper in PaperSize::ALL {
+                    ui.radio_value(&mut disc.paper, paper, paper.name());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut disc.format, DiscFormat::Png, "PNG");
+                ui.radio_value(&mut disc.format, DiscFormat::Svg, "SVG");
+            });
+            let ready = disc.burst.is_some() && !disc.is_running();
+            if ui
+                .add_enabled(ready, egui::Button::new("Export Disc"))
+                .clicked()
+            {
+                disc.start();
+            }
+            if let Some(last) = &disc.last {
+                ui.label(last);
+            }
+        });
+}
+
+// the most recent status reported by the thread running the video source
+#[derive(Resource)]
+pub struct VideoStatus(pub SourceStatus);
+
+impl Default for VideoStatus {
+    fn default() -> Self {
+        Self(SourceStatus::Streaming)
+    }
+}
+
+// a banner across the top of the display for as long as the video source is down
+pub fn gui_source_status(
+    mut ctx: EguiContexts,
+    mut cam_query: Query<&mut VideoStream>,
+    mut status: ResMut<VideoStatus>,
+) {
+    for mut cam in cam_query.iter_mut() {
+        if cam.refresh_controls() {
+            info!(
+                "The video source came back with {} controls",
+                cam.known_controls.len()
+            );
+        }
+        if let Some(latest) = cam.status_rx.drain().last() {
+            match &latest {
+                SourceStatus::Streaming => info!("The video source is streaming again"),
+                SourceStatus::Reconnecting { error, attempt, .. } => {
+                    warn!(
+                        "Reconnecting the video source (attempt {}): {}",
+                        attempt, error
+                    )
+                }
+            }
+            status.0 = latest;
+        }
+    }
+    if let SourceStatus::Reconnecting {
+        error,
+        attempt,
+        retry_in,
+    } = &status.0
+    {
+        egui::Area::new("source_status")
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 20.))
+            .show(ctx.ctx_mut(), |ui| {
+                egui::Frame::none()
+                    .fill(egui::Color32::DARK_RED)
+                    .inner_margin(12.)
+                    .rounding(6.)
+                    .show(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "Video source lost: {}\nReconnecting, attempt {} in {:.1}s",
+                                error,
+                                attempt,
+                                retry_in.as_secs_f32()
+                            ))
+                            .color(egui::Color32::WHITE)
+                            .size(24.),
+                        );
+                    });
+            });
+    }
+}
+
+// shown whether or not the rest of the gui is open, as the operator should know the feed is not as chosen
+pub fn gui_format_notice(mut ctx: EguiContexts, mut notice: ResMut<FormatNotice>) {
+    let mut dismissed = false;
+    if let Some(text) = &notice.0 {
+        egui::Window::new("Camera Format")
+            .collapsible(false)
+            .show(ctx.ctx_mut(), |ui| {
+                ui.label(text);
+                dismissed = ui.add(egui::Button::new("Dismiss")).clicked();
+            });
+    }
+    if dismissed {
+        notice.0 = None;
+    }
+}
+
 pub fn gui_set_crosshair(
     mut cross_query: Query<&mut Visibility, With<CameraCrosshairTag>>,
     ui_state: Res<UiState>,
@@ -281,7 +866,7 @@ pub fn gui_open(keyboard_input: Res<Input<KeyCode>>, mut ui_state: ResMut<UiStat
 
 pub fn gui_camera_control(
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Camera>>,
+    mut query: Query<&mut Transform, (With<Camera>, Without<OutputCamera>)>,
 ) {
     let mut transform = query.single_mut();
     let mut movement_speed: f32 = 1.;
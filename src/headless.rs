@@ -0,0 +1,228 @@
+// This is for driving the zoetrope from tests, with the test pattern in place of the camera and the crank
+// set by hand. Nothing happens on its own, the app only moves when it is updated or stepped.
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy::sprite::Mesh2dHandle;
+use bevy::time::TimeUpdateStrategy;
+use image::RgbaImage;
+
+use crate::bluetooth::RotationInterval;
+use crate::camera::VideoStream;
+use crate::capture::{CaptureRequests, OutputDestination};
+use crate::material::VideoMaterial;
+use crate::overlay::{SliceOverlay, SliceOverlaySprite};
+use crate::plugin::HeadlessPlugin;
+use crate::rotation::PlatterPhase;
+use crate::setup::RunningStates;
+use crate::strobe::{Strobe, StrobeShutter};
+use crate::zoetrope::{Preset, Slices, TickMode, ZoetropeImage, HEADLESS_SIZE};
+
+/// A zoetrope running without a window, stepped a tick at a time.
+pub struct Headless {
+    pub app: App,
+    // the turns are measured from where the platter started
+    start: Quat,
+}
+
+impl Headless {
+    pub fn new(slices: u8) -> Self {
+        Self::build(slices, false)
+    }
+
+    /// Also renders the output so that it can be read back, which needs a gpu adapter.
+    pub fn with_rendering(slices: u8) -> Self {
+        Self::build(slices, true)
+    }
+
+    fn build(slices: u8, render: bool) -> Self {
+        let mut app = App::new();
+        app.add_plugin(HeadlessPlugin { slices, render });
+        app.world
+            .resource_mut::<NextState<RunningStates>>()
+            .set(RunningStates::Running);
+        // the first update moves into the running state, where the zoetrope is set up
+        app.update();
+        // however long building took is forgotten, so that a tick starts right here
+        let mut fixed_time = app.world.resource_mut::<FixedTime>();
+        *fixed_time = FixedTime::new(fixed_time.period);
+        let mut headless = Self {
+            app,
+            start: Quat::IDENTITY,
+        };
+        headless.start = headless.platter_transform().rotation;
+        headless
+    }
+
+    /// Stands in for the reading from the arduino.
+    pub fn set_crank(&mut self, value: i8) {
+        self.app.world.resource_mut::<RotationInterval>().0 = value;
+    }
+
+    /// Changes the slice count like the gui does, updating once so that the tick follows it.
+    pub fn set_slices(&mut self, slices: u8) {
+        self.app.world.resource_mut::<Slices>().0 = slices;
+        self.update();
+    }
+
+    /// How long a fixed tick currently lasts.
+    pub fn tick_period(&self) -> Duration {
+        self.app.world.resource::<FixedTime>().period
+    }
+
+    /// Runs exactly this many fixed ticks of the animation.
+    pub fn step(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.world.run_schedule(CoreSchedule::FixedUpdate);
+        }
+    }
+
+    pub fn set_tick_mode(&mut self, mode: TickMode) {
+        *self.app.world.resource_mut::<TickMode>() = mode;
+        self.update();
+    }
+
+    pub fn set_strobe(&mut self, strobe: Strobe) {
+        *self.app.world.resource_mut::<Strobe>() = strobe;
+    }
+
+    /// Whether the strobe had the display blanked on the last frame.
+    pub fn strobe_blanked(&mut self) -> bool {
+        self.app
+            .world
+            .query_filtered::<&Visibility, With<StrobeShutter>>()
+            .single(&self.app.world)
+            == Visibility::Visible
+    }
+
+    pub fn set_overlay(&mut self, overlay: SliceOverlay) {
+        *self.app.world.resource_mut::<SliceOverlay>() = overlay;
+    }
+
+    /// Where the slice guides are and how big, `None` while they are hidden.
+    pub fn overlay(&mut self) -> Option<(Transform, Vec2)> {
+        let world = &mut self.app.world;
+        let (transform, sprite, visibility) = world
+            .query_filtered::<(&Transform, &Sprite, &Visibility), With<SliceOverlaySprite>>()
+            .single(world);
+        if *visibility != Visibility::Visible {
+            return None;
+        }
+        Some((*transform, sprite.custom_size?))
+    }
+
+    /// For calibrating as the buttons do, it shows on the platter at the next tick.
+    pub fn phase_mut(&mut self) -> Mut<PlatterPhase> {
+        self.app.world.resource_mut::<PlatterPhase>()
+    }
+
+    /// Runs a single frame, which picks up new frames from the source without moving the platter.
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// Runs a frame as if this much time had gone by since the last one, so that the fixed ticks happen
+    /// as they would when running for real.
+    pub fn update_after(&mut self, elapsed: Duration) {
+        let mut strategy = self.app.world.resource_mut::<TimeUpdateStrategy>();
+        if let TimeUpdateStrategy::ManualInstant(instant) = &mut *strategy {
+            *instant += elapsed;
+        }
+        self.update();
+    }
+
+    pub fn platter_transform(&mut self) -> Transform {
+        *self
+            .app
+            .world
+            .query_filtered::<&Transform, With<ZoetropeImage>>()
+            .single(&self.app.world)
+    }
+
+    /// How far the platter has turned about z since it was set up, in radians between -π and π.
+    pub fn platter_angle(&mut self) -> f32 {
+        let (axis, angle) =
+            (self.platter_transform().rotation * self.start.inverse()).to_axis_angle();
+        let angle = if axis.z < 0. { -angle } else { angle };
+        (angle + PI).rem_euclid(2. * PI) - PI
+    }
+
+    pub fn camera_transform(&mut self) -> Transform {
+        *self
+            .app
+            .world
+            .query_filtered::<&Transform, With<VideoStream>>()
+            .single(&self.app.world)
+    }
+
+    /// Does what the button in the presets window does, for a window of [`HEADLESS_SIZE`].
+    pub fn apply_preset(&mut self, preset: Preset) -> f32 {
+        let (radius, layout) = preset.layout(HEADLESS_SIZE, HEADLESS_SIZE);
+        let mesh: Mesh2dHandle = self
+            .app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(shape::Circle::new(radius).into())
+            .into();
+        let world = &mut self.app.world;
+        *world
+            .query_filtered::<&mut Mesh2dHandle, With<ZoetropeImage>>()
+            .single_mut(world) = mesh;
+        *world
+            .query_filtered::<&mut Transform, With<VideoStream>>()
+            .single_mut(world) = layout;
+        radius
+    }
+
+    /// Updates until a frame from the source is on the platter, returning whether one arrived in time.
+    pub fn wait_for_frame(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.update();
+            if self.has_frame() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    fn has_frame(&mut self) -> bool {
+        let world = &mut self.app.world;
+        let Ok(handle) = world
+            .query_filtered::<&Handle<VideoMaterial>, With<ZoetropeImage>>()
+            .get_single(world)
+        else {
+            return false;
+        };
+        world
+            .resource::<Assets<VideoMaterial>>()
+            .get(handle)
+            .is_some_and(|material| material.texture.is_some())
+    }
+
+    /// What would be on the window, read back from the gpu. Only works when made with
+    /// [`Headless::with_rendering`].
+    pub fn render_output(&mut self, timeout: Duration) -> Option<RgbaImage> {
+        self.app
+            .world
+            .resource_mut::<CaptureRequests>()
+            .request_output(OutputDestination::Memory);
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.update();
+            if let Some(image) = self
+                .app
+                .world
+                .resource_mut::<CaptureRequests>()
+                .latest_output
+                .take()
+            {
+                return Some(image);
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        None
+    }
+}
@@ -2,14 +2,36 @@ pub use nokhwa;
 mod audio;
 mod bluetooth;
 mod camera;
+mod capture;
+mod config;
+mod decode;
+mod disc;
 mod gui;
+mod headless;
+mod material;
+mod overlay;
+mod pattern;
+mod playback;
 mod plugin;
+mod profile;
+mod record;
+mod rotation;
 mod setup;
+mod stats;
+mod strobe;
 mod zoetrope;
 
 pub mod prelude {
     pub use crate::{
-        plugin::{AnimationPlugin, AudioPlugin, BluetoothPlugin, GuiPlugin, ZoetropePlugins},
-        setup::{cleanup_menu, setup_menu, Resolutions, RunningStates, Settings},
+        headless::Headless,
+        overlay::{OverlayFollow, SliceOverlay},
+        plugin::{
+            AnimationPlugin, AudioPlugin, BluetoothPlugin, CapturePlugin, GuiPlugin,
+            HeadlessPlugin, ZoetropePlugins,
+        },
+        rotation::PlatterPhase,
+        setup::{cleanup_menu, setup_menu, CameraFormats, Resolutions, RunningStates, Settings},
+        strobe::Strobe,
+        zoetrope::{Preset, TickMode, HEADLESS_SIZE},
     };
 }
@@ -0,0 +1,126 @@
+// This is for drawing frames that are still in the camera's own pixel layout, so that converting them to
+// rgb happens on the gpu rather than on the capture thread.
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::extract_resource::ExtractResource;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{AsBindGroup, Extent3d, ImageDataLayout, ShaderRef};
+use bevy::render::renderer::RenderQueue;
+use bevy::sprite::Material2d;
+use flume::{bounded, unbounded, Receiver, Sender};
+
+use crate::camera::PixelLayout;
+
+#[derive(AsBindGroup, TypeUuid, Debug, Clone, Default)]
+#[uuid = "5b0c7a2e-3f4d-4b8e-9a61-2d7e8f1c4a93"]
+pub struct VideoMaterial {
+    // one of `PixelLayout`, tells the shader how to read the texture
+    #[uniform(0)]
+    pub layout: u32,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Option<Handle<Image>>,
+}
+
+impl VideoMaterial {
+    pub fn set_layout(&mut self, layout: PixelLayout) {
+        self.layout = layout as u32;
+    }
+
+    pub fn layout(&self) -> PixelLayout {
+        match self.layout {
+            1 => PixelLayout::Yuyv,
+            2 => PixelLayout::Nv12,
+            _ => PixelLayout::Rgba,
+        }
+    }
+}
+
+impl Material2d for VideoMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/video.wgsl".into()
+    }
+}
+
+/// The frame on the zoetrope, kept on the cpu for snapshots as the texture asset is only set when the
+/// size or layout changes.
+#[derive(Resource, Default)]
+pub struct ShownFrame {
+    pub image: Option<Arc<Image>>,
+    pub layout: PixelLayout,
+}
+
+/// Frames that fit the texture already on the gpu, shared with the render world so that it can write them
+/// straight into it. Changing the image asset instead makes the texture and its bind group again.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct FrameUpload {
+    sender: Sender<(Handle<Image>, Arc<Image>)>,
+    // also kept here so that a frame the render world never took, as when running headless, is replaced
+    receiver: Receiver<(Handle<Image>, Arc<Image>)>,
+    // how long each write into the texture took, sent back from the render world
+    upload_times_tx: Sender<Duration>,
+    upload_times_rx: Receiver<Duration>,
+}
+
+impl Default for FrameUpload {
+    fn default() -> Self {
+        let (sender, receiver) = bounded(1);
+        let (upload_times_tx, upload_times_rx) = unbounded();
+        Self {
+            sender,
+            receiver,
+            upload_times_tx,
+            upload_times_rx,
+        }
+    }
+}
+
+impl FrameUpload {
+    pub fn send(&self, texture: Handle<Image>, image: Arc<Image>) {
+        let _ = self.receiver.try_recv();
+        let _ = self.sender.try_send((texture, image));
+    }
+
+    /// How long the frames written since this was last called took to upload.
+    pub fn upload_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.upload_times_rx.try_iter()
+    }
+}
+
+/// Runs in the render world once the image assets are on the gpu.
+pub fn upload_frame(
+    upload: Res<FrameUpload>,
+    gpu_images: Res<RenderAssets<Image>>,
+    queue: Res<RenderQueue>,
+) {
+    let Ok((texture, image)) = upload.receiver.try_recv() else {
+        return;
+    };
+    let Some(gpu_image) = gpu_images.get(&texture) else {
+        return;
+    };
+    let size = image.texture_descriptor.size;
+    // the texture is made again if the asset changed size in the meantime, and this frame is dropped
+    if gpu_image.size != Vec2::new(size.width as f32, size.height as f32) || size.height == 0 {
+        return;
+    }
+    let started = Instant::now();
+    queue.write_texture(
+        gpu_image.texture.as_image_copy(),
+        &image.data,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(image.data.len() as u32 / size.height),
+            rows_per_image: None,
+        },
+        Extent3d {
+            depth_or_array_layers: 1,
+            ..size
+        },
+    );
+    let _ = upload.upload_times_tx.send(started.elapsed());
+}
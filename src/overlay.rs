@@ -0,0 +1,183 @@
+// This is for drawing guides over the platter so that figures can be placed exactly one slice apart: a line
+// between every slice, the number of each slice and rings to line things up at the same distance out.
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::view::RenderLayers;
+use bevy::sprite::Mesh2dHandle;
+
+use crate::capture::WINDOW_ONLY_LAYER;
+use crate::pattern::draw_number;
+use crate::zoetrope::{platter_facing, Slices, ZoetropeImage};
+
+const OVERLAY_SIZE: u32 = 1024;
+// half the width of the lines, in pixels of the overlay
+const LINE_PX: f32 = 1.5;
+const LINE_COLOR: [u8; 4] = [255, 255, 255, 200];
+const NUMBER_COLOR: [u8; 3] = [255, 220, 0];
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OverlayFollow {
+    // turns along with the platter, so each number stays on its own slice
+    Platter,
+    // stays where it is while the platter turns under it
+    Camera,
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct SliceOverlay {
+    pub enabled: bool,
+    pub follow: OverlayFollow,
+    // guide rings between the center and the edge, the edge always has one
+    pub rings: u8,
+}
+
+impl Default for SliceOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            follow: OverlayFollow::Platter,
+            rings: 3,
+        }
+    }
+}
+
+/// The overlay sprite, along with the slices and rings that its image was drawn for.
+#[derive(Component)]
+pub struct SliceOverlaySprite {
+    drawn: (u8, u8),
+}
+
+pub fn slice_overlay_setup(
+    mut commands: Commands,
+    overlay: Res<SliceOverlay>,
+    slices: Res<Slices>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    commands
+        .spawn(SpriteBundle {
+            texture: images.add(overlay_image(slices.0, overlay.rings)),
+            // above the platter but below the strobe and crosshair
+            transform: Transform::from_xyz(0., 0., 3.),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        // only there to line things up, so it is left out of captures like the crosshair
+        .insert(RenderLayers::layer(WINDOW_ONLY_LAYER))
+        .insert(SliceOverlaySprite {
+            drawn: (slices.0, overlay.rings),
+        });
+}
+
+pub fn slice_overlay_teardown(
+    mut commands: Commands,
+    query: Query<Entity, With<SliceOverlaySprite>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// drawing the guides goes over every pixel, so it is only done again when the slices or rings change
+pub fn slice_overlay_redraw(
+    overlay: Res<SliceOverlay>,
+    slices: Res<Slices>,
+    mut images: ResMut<Assets<Image>>,
+    mut query: Query<(&Handle<Image>, &mut SliceOverlaySprite)>,
+) {
+    let wanted = (slices.0, overlay.rings);
+    for (texture, mut sprite) in query.iter_mut() {
+        if sprite.drawn == wanted {
+            continue;
+        }
+        sprite.drawn = wanted;
+        if let Some(image) = images.get_mut(texture) {
+            *image = overlay_image(slices.0, overlay.rings);
+        }
+    }
+}
+
+// sized to the platter every frame, as the presets swap its mesh for one of a different size
+pub fn slice_overlay_follow(
+    overlay: Res<SliceOverlay>,
+    meshes: Res<Assets<Mesh>>,
+    platter: Query<(&Transform, &Mesh2dHandle), (With<ZoetropeImage>, Without<SliceOverlaySprite>)>,
+    mut query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<SliceOverlaySprite>>,
+) {
+    let Ok((platter, mesh)) = platter.get_single() else {
+        return;
+    };
+    let radius = meshes
+        .get(&mesh.0)
+        .and_then(|mesh| mesh.compute_aabb())
+        .map_or(0., |aabb| aabb.half_extents.x);
+    for (mut transform, mut sprite, mut visibility) in query.iter_mut() {
+        visibility.set_if_neq(if overlay.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        });
+        transform.translation.x = platter.translation.x;
+        transform.translation.y = platter.translation.y;
+        // the platter faces away from the camera, so only the turning since it was set up is taken
+        transform.rotation = match overlay.follow {
+            OverlayFollow::Platter => platter.rotation * platter_facing().inverse(),
+            OverlayFollow::Camera => Quat::IDENTITY,
+        };
+        let size = Some(Vec2::splat(radius * 2.));
+        if sprite.custom_size != size {
+            sprite.custom_size = size;
+        }
+    }
+}
+
+fn overlay_image(slices: u8, rings: u8) -> Image {
+    let size = OVERLAY_SIZE as usize;
+    let half = size as f32 / 2.;
+    let slice_angle = 2. * PI / slices.max(1) as f32;
+    // the edge is pulled in by a line so that it is not cut in half
+    let edge = half - LINE_PX;
+    let ring_radii: Vec<f32> = (1..=rings as u32 + 1)
+        .map(|ring| edge * ring as f32 / (rings as u32 + 1) as f32)
+        .collect();
+
+    let mut data = vec![0; size * size * 4];
+    for (i, px) in data.chunks_exact_mut(4).enumerate() {
+        let x = (i % size) as f32 + 0.5 - half;
+        let y = half - ((i / size) as f32 + 0.5);
+        let radius = (x * x + y * y).sqrt();
+        if radius > half {
+            continue;
+        }
+        // how far off the nearest slice boundary, measured along the circle
+        let into_slice = y.atan2(x).rem_euclid(slice_angle);
+        let off_line = into_slice.min(slice_angle - into_slice) * radius;
+        let on_line = slices > 1 && off_line < LINE_PX;
+        let on_ring = ring_radii.iter().any(|r| (radius - r).abs() < LINE_PX);
+        if on_line || on_ring {
+            px.copy_from_slice(&LINE_COLOR);
+        }
+    }
+
+    // numbered in the middle of each slice, just inside the edge
+    let scale = size / 150;
+    for slice in 0..slices.max(1) {
+        let theta = slice_angle * (slice as f32 + 0.5);
+        let r = 0.88 * half;
+        let cx = (half + r * theta.cos()) as usize;
+        let cy = (half - r * theta.sin()) as usize;
+        draw_number(&mut data, size, cx, cy, slice as u64, scale, NUMBER_COLOR);
+    }
+
+    Image::new(
+        Extent3d {
+            width: OVERLAY_SIZE,
+            height: OVERLAY_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
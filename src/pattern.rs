@@ -0,0 +1,244 @@
+// This is a synthetic frame source so that the zoetrope can be run without a physical camera attached.
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use nokhwa::utils::{CameraControl, KnownCameraControl};
+
+use crate::camera::{CameraSetting, Frame, FrameSource, PixelLayout};
+
+pub const PATTERN_SIZE: u32 = 1080;
+pub const PATTERN_FRAME_RATE: u32 = 30;
+
+// 75% SMPTE color bars, left to right
+const BARS: [[u8; 3]; 7] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+];
+
+// 3x5 bitmap digits, each row is the low 3 bits from left to right
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+pub struct TestPattern {
+    slices: u8,
+    frame: u64,
+    last_frame: Option<Instant>,
+    // polar coordinates of every pixel, computed once as the wheel only changes by an offset
+    polar: Vec<(f32, f32)>,
+}
+
+impl TestPattern {
+    pub fn new(slices: u8) -> Self {
+        let half = PATTERN_SIZE as f32 / 2.;
+        let polar = (0..PATTERN_SIZE * PATTERN_SIZE)
+            .map(|i| {
+                let x = (i % PATTERN_SIZE) as f32 + 0.5 - half;
+                let y = half - ((i / PATTERN_SIZE) as f32 + 0.5);
+                (
+                    y.atan2(x).rem_euclid(2. * PI),
+                    (x * x + y * y).sqrt() / half,
+                )
+            })
+            .collect();
+        Self {
+            slices: slices.max(1),
+            frame: 0,
+            last_frame: None,
+            polar,
+        }
+    }
+
+    fn render(&self) -> Vec<u8> {
+        let size = PATTERN_SIZE as usize;
+        let mut data = vec![0; size * size * 4];
+        // one full turn of the wheel every ten seconds
+        let offset = 2. * PI * (self.frame as f32 / (PATTERN_FRAME_RATE * 10) as f32);
+        let slice_angle = 2. * PI / self.slices as f32;
+
+        for (px, (angle, radius)) in data.chunks_exact_mut(4).zip(self.polar.iter()) {
+            let rgb = if *radius > 1. {
+                [0, 0, 0]
+            } else {
+                // the slice boundaries are fixed so the rotation of the wheel can be seen against them
+                let into_slice = angle.rem_euclid(slice_angle) * radius;
+                if into_slice < 0.004 && *radius > 0.3 {
+                    [255, 255, 255]
+                } else {
+                    hsv_to_rgb(
+                        (angle + offset).rem_euclid(2. * PI) / (2. * PI),
+                        *radius,
+                        1.,
+                    )
+                }
+            };
+            px.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+        }
+
+        // color bars across the middle of the disc
+        let bar_width = size / 2 / BARS.len();
+        let bars_left = size / 2 - bar_width * BARS.len() / 2;
+        let bars_top = size / 2 - size / 16;
+        for (i, color) in BARS.iter().enumerate() {
+            fill_rect(
+                &mut data,
+                size,
+                bars_left + i * bar_width,
+                bars_top,
+                bar_width,
+                size / 16,
+                *color,
+            );
+        }
+
+        // slice numbers around the rim, placed in the middle of each slice
+        let scale = size / 180;
+        for slice in 0..self.slices {
+            let theta = slice_angle * (slice as f32 + 0.5);
+            let r = 0.85 * size as f32 / 2.;
+            let cx = (size as f32 / 2. + r * theta.cos()) as usize;
+            let cy = (size as f32 / 2. - r * theta.sin()) as usize;
+            draw_number(&mut data, size, cx, cy, slice as u64, scale, [0, 0, 0]);
+        }
+
+        // frame counter just below the bars
+        draw_number(
+            &mut data,
+            size,
+            size / 2,
+            bars_top + size / 8,
+            self.frame,
+            scale * 2,
+            [255, 255, 255],
+        );
+        data
+    }
+}
+
+impl FrameSource for TestPattern {
+    fn open(&mut self) -> Result<()> {
+        self.frame = 0;
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        // hold each frame for as long as a camera running at the same rate would
+        let period = Duration::from_secs_f32(1. / PATTERN_FRAME_RATE as f32);
+        if let Some(last) = self.last_frame {
+            if let Some(remaining) = period.checked_sub(last.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+        let captured_at = Instant::now();
+        self.last_frame = Some(captured_at);
+
+        let image = Image::new(
+            Extent3d {
+                width: PATTERN_SIZE,
+                height: PATTERN_SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.render(),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        self.frame += 1;
+        Ok(Some(Frame {
+            image,
+            layout: PixelLayout::Rgba,
+            captured_at,
+            decode_time: captured_at.elapsed(),
+        }))
+    }
+
+    fn apply_control(&mut self, _setting: CameraSetting) -> Result<()> {
+        // there is nothing to tune on a generated image
+        Ok(())
+    }
+
+    fn controls(&self) -> Result<BTreeMap<KnownCameraControl, CameraControl>> {
+        Ok(BTreeMap::new())
+    }
+
+    fn set_slices(&mut self, slices: u8) {
+        self.slices = slices.max(1);
+    }
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+    let i = (h * 6.).floor();
+    let f = h * 6. - i;
+    let (p, q, t) = (v * (1. - s), v * (1. - f * s), v * (1. - (1. - f) * s));
+    let (r, g, b) = match i as u32 % 6 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+    [(r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8]
+}
+
+// fills a rectangle in a square RGBA buffer, clipping anything outside of it
+fn fill_rect(data: &mut [u8], size: usize, x: usize, y: usize, w: usize, h: usize, rgb: [u8; 3]) {
+    for row in y.min(size)..(y + h).min(size) {
+        for col in x.min(size)..(x + w).min(size) {
+            let i = (row * size + col) * 4;
+            data[i..i + 4].copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+        }
+    }
+}
+
+/// Draws `value` centered on `(cx, cy)` in a square RGBA buffer of side `size` using the built in
+/// 3x5 digit font, with every font pixel drawn as a `scale` by `scale` block.
+pub fn draw_number(
+    data: &mut [u8],
+    size: usize,
+    cx: usize,
+    cy: usize,
+    value: u64,
+    scale: usize,
+    rgb: [u8; 3],
+) {
+    let text = value.to_string();
+    let scale = scale.max(1);
+    let width = text.len() * 4 * scale - scale;
+    let left = cx.saturating_sub(width / 2);
+    let top = cy.saturating_sub(5 * scale / 2);
+    for (n, digit) in text.bytes().map(|b| (b - b'0') as usize).enumerate() {
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    fill_rect(
+                        data,
+                        size,
+                        left + (n * 4 + col) * scale,
+                        top + row * scale,
+                        scale,
+                        scale,
+                        rgb,
+                    );
+                }
+            }
+        }
+    }
+}
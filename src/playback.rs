@@ -0,0 +1,260 @@
+// This is for playing pre-rendered animation loops through the zoetrope instead of a live camera.
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, ImageFormat, RgbaImage};
+use nokhwa::utils::{CameraControl, KnownCameraControl};
+
+use crate::camera::{CameraSetting, DecodeError, Frame, FrameSource};
+
+// kept outside of `assets` so that large clips are not embedded into the binary
+pub const PLAYBACK_DIR: &str = "./playback";
+// decoded frames kept around, so that rocking the crank back and forth does not decode them every time
+const CACHED_FRAMES: usize = 8;
+
+// where the frames of a clip come from, only decoded as they are shown
+enum Frames {
+    // every frame of a gif is drawn over the one before it, so they are decoded up front
+    Decoded(Vec<Image>),
+    Files(Vec<PathBuf>),
+    // the whole file, and where each jpeg is in it
+    Jpegs(Vec<u8>, Vec<Range<usize>>),
+}
+
+impl Frames {
+    fn len(&self) -> usize {
+        match self {
+            Self::Decoded(images) => images.len(),
+            Self::Files(paths) => paths.len(),
+            Self::Jpegs(_, ranges) => ranges.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn decode(&self, index: usize) -> Result<Image, DecodeError> {
+        let decoded = match self {
+            Self::Decoded(images) => return Ok(images[index].clone()),
+            Self::Files(paths) => image::open(&paths[index]),
+            Self::Jpegs(data, ranges) => {
+                image::load_from_memory_with_format(&data[ranges[index].clone()], ImageFormat::Jpeg)
+            }
+        };
+        decoded
+            .map(|image| square_image(image.to_rgba8()))
+            .map_err(|why| DecodeError(why.to_string()))
+    }
+}
+
+pub struct Playback {
+    path: PathBuf,
+    frames: Frames,
+    cache: VecDeque<(usize, Image)>,
+    // fractional frame position, moved along by the crank
+    cursor: f32,
+    shown: Option<usize>,
+}
+
+impl Playback {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            frames: Frames::Decoded(Vec::new()),
+            cache: VecDeque::new(),
+            cursor: 0.,
+            shown: None,
+        }
+    }
+}
+
+impl FrameSource for Playback {
+    fn open(&mut self) -> Result<()> {
+        let frames = load_frames(&self.path)?;
+        if frames.is_empty() {
+            bail!("{} does not contain any frames", self.path.display());
+        }
+        info!("Found {} frames in {}", frames.len(), self.path.display());
+        self.frames = frames;
+        self.cache.clear();
+        self.cursor = 0.;
+        self.shown = None;
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        // only hand over a frame when the crank has moved onto a new one
+        let index = self.cursor.floor() as usize % self.frames.len();
+        if self.shown == Some(index) {
+            return Ok(None);
+        }
+        self.shown = Some(index);
+        if let Some((_, image)) = self.cache.iter().find(|(cached, _)| *cached == index) {
+            return Ok(Some(Frame::now(image.clone())));
+        }
+        let image = self.frames.decode(index)?;
+        if self.cache.len() == CACHED_FRAMES {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((index, image.clone()));
+        Ok(Some(Frame::now(image)))
+    }
+
+    fn apply_control(&mut self, _setting: CameraSetting) -> Result<()> {
+        // the frames are already rendered, so there is nothing to tune
+        Ok(())
+    }
+
+    fn controls(&self) -> Result<BTreeMap<KnownCameraControl, CameraControl>> {
+        Ok(BTreeMap::new())
+    }
+
+    fn advance(&mut self, frames: f32) {
+        self.cursor = (self.cursor + frames).rem_euclid(self.frames.len().max(1) as f32);
+    }
+}
+
+/// Lists everything in [`PLAYBACK_DIR`] that looks like it can be played back.
+pub fn available_playbacks() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = std::fs::read_dir(PLAYBACK_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.is_dir()
+                        || matches!(
+                            extension(path).as_deref(),
+                            Some("gif") | Some("avi") | Some("mjpeg") | Some("mjpg")
+                        )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    found.sort();
+    found
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
+
+fn load_frames(path: &Path) -> Result<Frames> {
+    if path.is_dir() {
+        return Ok(Frames::Files(list_sequence(path)?));
+    }
+    match extension(path).as_deref() {
+        Some("gif") => {
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+            Ok(Frames::Decoded(
+                decoder
+                    .into_frames()
+                    .collect_frames()?
+                    .into_iter()
+                    .map(|frame| square_image(frame.into_buffer()))
+                    .collect(),
+            ))
+        }
+        Some("avi") | Some("mjpeg") | Some("mjpg") => {
+            let data = std::fs::read(path)?;
+            let jpegs = find_jpegs(&data);
+            Ok(Frames::Jpegs(data, jpegs))
+        }
+        _ => Err(anyhow!(
+            "{} is not a supported playback format",
+            path.display()
+        )),
+    }
+}
+
+// a folder of numbered PNGs, played in numeric rather than lexical order so that 10.png follows 9.png
+fn list_sequence(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| extension(path).as_deref() == Some("png"))
+        .map(|path| {
+            let number = path
+                .file_stem()
+                .map(|stem| {
+                    stem.to_string_lossy()
+                        .chars()
+                        .filter(char::is_ascii_digit)
+                        .collect::<String>()
+                })
+                .and_then(|digits| digits.parse().ok())
+                .unwrap_or(u64::MAX);
+            (number, path)
+        })
+        .collect();
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+// walks the RIFF chunks of an AVI for every video chunk, a bare MJPEG stream is split on the JPEG start
+// and end markers instead. the frames are only decoded when they are shown
+fn find_jpegs(data: &[u8]) -> Vec<Range<usize>> {
+    let mut jpegs = Vec::new();
+    if data.len() > 12 && data.starts_with(b"RIFF") {
+        collect_avi_chunks(data, 12, data.len(), &mut jpegs);
+    } else {
+        let mut start = None;
+        for (i, pair) in data.windows(2).enumerate() {
+            match (pair[0], pair[1], start) {
+                (0xFF, 0xD8, None) => start = Some(i),
+                (0xFF, 0xD9, Some(s)) => {
+                    jpegs.push(s..i + 2);
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    jpegs
+}
+
+// the chunks between `at` and `end`, as offsets into the whole file
+fn collect_avi_chunks(data: &[u8], mut at: usize, end: usize, out: &mut Vec<Range<usize>>) {
+    while at + 8 <= end {
+        let id = &data[at..at + 4];
+        let size = u32::from_le_bytes([data[at + 4], data[at + 5], data[at + 6], data[at + 7]]);
+        let size = size as usize;
+        let body = at + 8..(at + 8 + size).min(end);
+        if id == b"LIST" && body.len() >= 4 {
+            collect_avi_chunks(data, body.start + 4, body.end, out);
+        } else if (&id[2..4] == b"dc" || &id[2..4] == b"db") && !body.is_empty() {
+            out.push(body);
+        }
+        // chunks are padded to an even length
+        let next = at + 8 + size + (size & 1);
+        if next > end {
+            break;
+        }
+        at = next;
+    }
+}
+
+// crops the largest centered square out of a frame, as the zoetrope only ever shows a circle
+fn square_image(mut frame: RgbaImage) -> Image {
+    let side = frame.width().min(frame.height());
+    let (x, y) = ((frame.width() - side) / 2, (frame.height() - side) / 2);
+    Image::new(
+        Extent3d {
+            width: side,
+            height: side,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        image::imageops::crop(&mut frame, x, y, side, side)
+            .to_image()
+            .into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
@@ -1,33 +1,62 @@
-use std::time::Duration;
+use std::time::Instant;
 
 use bevy::app::PluginGroupBuilder;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
-use bevy::window::WindowLevel;
+use bevy::render::extract_resource::ExtractResourcePlugin;
+use bevy::render::render_asset::PrepareAssetSet;
+use bevy::render::settings::{Backends, WgpuSettings};
+use bevy::render::{RenderApp, RenderPlugin, RenderSet};
+use bevy::sprite::Material2dPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::{ExitCondition, WindowLevel};
+use bevy::winit::WinitPlugin;
 use bevy_egui::EguiPlugin;
 use bevy_embedded_assets::EmbeddedAssetPlugin;
 use bevy_kira_audio::prelude::AudioPlugin as KiraAudioPlugin;
 use bevy_tokio_tasks::TokioTasksPlugin;
 
 use crate::audio::{
-    audio_modulation_rotation, audio_setup, change_audio_volume, Song, VolumeEvent,
+    audio_modulation_rotation, audio_setup, audio_teardown, change_audio_volume, Song, VolumeEvent,
 };
 use crate::bluetooth::{
     async_converter_arduino_finder, async_converter_arduino_reader, ArduinoConnected,
     RotationInterval,
 };
-use crate::camera::ColorSettings;
+use crate::camera::{ColorSettings, FormatNotice, VideoSource};
+use crate::capture::{
+    capture_burst, capture_hotkeys, capture_snapshot, dispatch_output_frames, output_camera_follow,
+    output_capture_demand, output_capture_setup, output_capture_teardown, output_readback,
+    readback_output, CaptureRequests, OutputReadback,
+};
+use crate::disc::DiscExport;
 use crate::gui::{
-    cursor_visibility, gui_camera_control, gui_full, gui_open, gui_set_crosshair, CameraCrosshair,
-    UiState, Volume,
+    cursor_visibility, gui_animation, gui_calibration, gui_camera_control, gui_capture, gui_crop,
+    gui_format_notice, gui_full, gui_open, gui_rotation_speed, gui_set_crosshair,
+    gui_source_status, CameraCrosshair, UiState, VideoStatus, Volume,
+};
+use crate::material::{upload_frame, FrameUpload, ShownFrame, VideoMaterial};
+use crate::overlay::{
+    slice_overlay_follow, slice_overlay_redraw, slice_overlay_setup, slice_overlay_teardown,
+    SliceOverlay,
+};
+use crate::profile::{CameraProfiles, ProfileName};
+use crate::record::{
+    record_finish, record_frames, record_request, record_teardown, record_tick, Recording,
+    RecordingFrame,
 };
+use crate::rotation::{PlatterPhase, ResponseCurve};
 use crate::setup::{
-    cleanup_menu, setup_menu, update_scale_factor, Resolutions, RunningStates, Settings,
-    StringBuffer,
+    cleanup_menu, setup_menu, update_scale_factor, CameraFormats, Resolutions, RunningStates,
+    Settings, StringBuffer,
 };
+use crate::stats::{capture_diagnostics, capture_diagnostics_setup, CaptureStats};
+use crate::strobe::{strobe_setup, strobe_shutter, strobe_teardown, Strobe};
 use crate::zoetrope::{
-    zoetrope_animation, zoetrope_next_camera_frame, zoetrope_setup, RotationDirection, Slices,
-    ZoetropeAnimationThresholdSpeed,
+    slice_period, zoetrope_animation, zoetrope_next_camera_frame, zoetrope_setup,
+    zoetrope_teardown, zoetrope_tick_changed, zoetrope_tick_per_frame, RotationDirection, Slices,
+    TickMode, ZoetropeAnimationThresholdSpeed,
 };
 
 pub struct ZoetropePlugins; // High level Grouped Plugins for end use
@@ -35,6 +64,13 @@ pub struct BluetoothPlugin; // Bluetooth only section
 pub struct GuiPlugin; // Gui controls and setup
 pub struct AnimationPlugin; // Plugin for the animation and its controls
 pub struct AudioPlugin; // Plugin for playing the music
+pub struct CapturePlugin; // Saving stills and recordings of the camera and the window
+pub struct HeadlessPlugin {
+    // Everything needed to run the zoetrope without a window, camera or crank, for tests
+    pub slices: u8,
+    // also brings up the renderer so that the output can be read back, which needs a gpu adapter
+    pub render: bool,
+}
 struct BasePlugin; // Miscellaneous and background things that need to be set for the typical ZoetropePlugins
 struct SetupPlugin; // Things that run within the setup window before the actual Zoetrope things
 
@@ -59,11 +95,11 @@ impl Plugin for SetupPlugin {
         .add_plugin(TokioTasksPlugin::default())
         .add_plugin(EguiPlugin)
         .insert_resource(StringBuffer(String::default()))
+        .insert_resource(CameraFormats::default())
         .insert_resource(Resolutions::default())
         .insert_resource(Settings {
-            camera: nokhwa::utils::CameraIndex::default(),
-            resolution: nokhwa::utils::Resolution::default(),
-            frame_rate: 0,
+            source: None,
+            format: None,
             arduino_connection: false,
             song: None,
         })
@@ -85,11 +121,54 @@ impl Plugin for SetupPlugin {
     }
 }
 
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let backends = if self.render {
+            Some(Backends::all())
+        } else {
+            None
+        };
+        app.add_plugins(
+            DefaultPlugins
+                .build()
+                .disable::<WinitPlugin>()
+                .disable::<LogPlugin>()
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    wgpu_settings: WgpuSettings {
+                        backends,
+                        ..default()
+                    },
+                }),
+        )
+        // time stands still so that the fixed ticks only happen when they are stepped by hand
+        .insert_resource(TimeUpdateStrategy::ManualInstant(Instant::now()))
+        .insert_resource(Settings {
+            source: Some(VideoSource::TestPattern),
+            format: None,
+            arduino_connection: true,
+            song: None,
+        })
+        .insert_resource(Slices(self.slices))
+        .insert_resource(ArduinoConnected(true))
+        .insert_resource(RotationInterval(0))
+        .insert_resource(ColorSettings::default())
+        .add_state::<RunningStates>()
+        .add_plugin(BasePlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(CapturePlugin);
+    }
+}
+
 impl Plugin for BasePlugin {
     fn build(&self, app: &mut App) {
+        // only a starting point, `zoetrope_tick_changed` keeps it in step with the slices from then on
         let slices = app.world.get_resource::<Slices>().unwrap().0;
-        *app.world.get_resource_mut::<FixedTime>().unwrap() =
-            FixedTime::new(Duration::from_millis((1. / (slices as f32) * 1000.) as u64));
+        *app.world.get_resource_mut::<FixedTime>().unwrap() = FixedTime::new(slice_period(slices));
         app.insert_resource(ClearColor(Color::BLACK))
             .add_system(bevy::window::close_on_esc);
     }
@@ -106,12 +185,45 @@ impl Plugin for BluetoothPlugin {
 impl Plugin for AnimationPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ZoetropeAnimationThresholdSpeed(5))
+            .insert_resource(ResponseCurve::default())
+            .insert_resource(PlatterPhase::default())
+            .insert_resource(FormatNotice::default())
+            .insert_resource(CaptureStats::default())
+            // draws the zoetrope, converting raw camera formats on the gpu
+            .add_plugin(Material2dPlugin::<VideoMaterial>::default())
+            .insert_resource(ShownFrame::default())
+            .insert_resource(FrameUpload::default())
+            .add_plugin(ExtractResourcePlugin::<FrameUpload>::default())
             .insert_resource(RotationDirection {
                 audio: crate::zoetrope::Direction::CW,
                 animation: crate::zoetrope::Direction::CW,
             })
             .add_system(zoetrope_setup.in_schedule(OnEnter(RunningStates::Running)))
+            .add_system(zoetrope_teardown.in_schedule(OnExit(RunningStates::Running)))
             .add_system(zoetrope_next_camera_frame.in_set(OnUpdate(RunningStates::Running)))
+            .insert_resource(TickMode::default())
+            .add_system(zoetrope_tick_changed.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(zoetrope_tick_per_frame.in_base_set(CoreSet::PreUpdate))
+            .insert_resource(Strobe::default())
+            .add_system(strobe_setup.in_schedule(OnEnter(RunningStates::Running)))
+            .add_system(strobe_teardown.in_schedule(OnExit(RunningStates::Running)))
+            .add_system(strobe_shutter.in_set(OnUpdate(RunningStates::Running)))
+            .insert_resource(SliceOverlay::default())
+            .add_system(slice_overlay_setup.in_schedule(OnEnter(RunningStates::Running)))
+            .add_system(slice_overlay_teardown.in_schedule(OnExit(RunningStates::Running)))
+            .add_system(slice_overlay_redraw.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(
+                slice_overlay_follow
+                    .after(zoetrope_animation)
+                    .in_set(OnUpdate(RunningStates::Running)),
+            )
+            // reported next to the frame time diagnostics
+            .add_startup_system(capture_diagnostics_setup)
+            .add_system(
+                capture_diagnostics
+                    .after(zoetrope_next_camera_frame)
+                    .in_set(OnUpdate(RunningStates::Running)),
+            )
             // the line below is for a debug system in which a static image is displayed instead of the
             // camera being used.
             // .add_system(zoetrope_next_frame_static.in_set(OnUpdate(RunningStates::Running)))
@@ -120,6 +232,9 @@ impl Plugin for AnimationPlugin {
                     .in_set(OnUpdate(RunningStates::Running))
                     .in_schedule(CoreSchedule::FixedUpdate),
             );
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_system(upload_frame.in_set(PrepareAssetSet::PostAssetPrepare));
+        }
     }
 }
 
@@ -130,8 +245,18 @@ impl Plugin for GuiPlugin {
         })
         .insert_resource(ColorSettings::default())
         .insert_resource(CameraCrosshair(false))
+        .insert_resource(CameraProfiles::default())
+        .insert_resource(ProfileName::default())
+        .insert_resource(VideoStatus::default())
         .insert_resource(Volume::default())
         .add_system(gui_full.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_rotation_speed.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_animation.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_calibration.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_crop.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_capture.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_format_notice.in_set(OnUpdate(RunningStates::Running)))
+        .add_system(gui_source_status.in_set(OnUpdate(RunningStates::Running)))
         .add_system(cursor_visibility.in_set(OnUpdate(RunningStates::Running)))
         .add_system(gui_open.in_set(OnUpdate(RunningStates::Running)))
         .add_system(gui_camera_control.in_set(OnUpdate(RunningStates::Running)))
@@ -139,12 +264,62 @@ impl Plugin for GuiPlugin {
     }
 }
 
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        let (readback, frames) = output_readback();
+        app.insert_resource(readback)
+            .insert_resource(frames)
+            .insert_resource(CaptureRequests::default())
+            .insert_resource(Recording::default())
+            .insert_resource(DiscExport::default())
+            .add_event::<RecordingFrame>()
+            .add_plugin(ExtractResourcePlugin::<OutputReadback>::default())
+            .add_system(output_capture_setup.in_schedule(OnEnter(RunningStates::Running)))
+            .add_system(output_capture_teardown.in_schedule(OnExit(RunningStates::Running)))
+            .add_system(capture_hotkeys.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(capture_snapshot.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(output_camera_follow.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(record_request.in_set(OnUpdate(RunningStates::Running)))
+            // recordings can still be writing out after going back to the setup menu
+            .add_system(record_finish)
+            .add_system(
+                output_capture_demand
+                    .after(capture_snapshot)
+                    .after(record_request)
+                    .in_set(OnUpdate(RunningStates::Running)),
+            )
+            .add_system(dispatch_output_frames.in_set(OnUpdate(RunningStates::Running)))
+            .add_system(
+                record_frames
+                    .after(dispatch_output_frames)
+                    .in_set(OnUpdate(RunningStates::Running)),
+            )
+            .add_system(record_teardown.in_schedule(OnExit(RunningStates::Running)))
+            .add_system(
+                record_tick
+                    .after(zoetrope_animation)
+                    .in_set(OnUpdate(RunningStates::Running))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                capture_burst
+                    .after(zoetrope_animation)
+                    .in_set(OnUpdate(RunningStates::Running))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_system(readback_output.in_set(RenderSet::Cleanup));
+        }
+    }
+}
+
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<VolumeEvent>()
             .add_plugin(KiraAudioPlugin)
             .insert_resource(Song("None".to_string()))
             .add_system(audio_setup.in_schedule(OnEnter(RunningStates::Running)))
+            .add_system(audio_teardown.in_schedule(OnExit(RunningStates::Running)))
             .add_system(audio_modulation_rotation.in_set(OnUpdate(RunningStates::Running)))
             .add_system(change_audio_volume.in_set(OnUpdate(RunningStates::Running)));
     }
@@ -157,6 +332,7 @@ impl PluginGroup for ZoetropePlugins {
             .add(BasePlugin)
             .add(AudioPlugin)
             .add(AnimationPlugin)
+            .add(CapturePlugin)
             .add(GuiPlugin)
             .add(BluetoothPlugin)
     }
@@ -0,0 +1,109 @@
+// This is for saving the tuning of a camera so that it does not have to be redone every time the zoetrope starts.
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use bevy::prelude::*;
+use nokhwa::utils::{CameraFormat, FrameFormat, Resolution};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{save_crop, ColorSettings, CropRegion, VideoStream};
+use crate::config::{load_config, save_config};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub color: ColorSettings,
+    pub crop: Option<CropRegion>,
+    pub format: Option<SavedFormat>,
+}
+
+impl Profile {
+    /// Puts the profile's crop on the stream, and keeps it as the camera's saved crop as well so that the
+    /// crop window and the next start agree with it.
+    pub fn apply_crop(&self, camera: &str, cam: &mut VideoStream) {
+        let Some(crop) = self.crop else {
+            return;
+        };
+        cam.set_crop(crop);
+        if let Err(e) = save_crop(camera, crop) {
+            warn!("Could not save the crop: {}", e);
+        }
+    }
+}
+
+// nokhwa's format types are not serializable, so the parts of them that matter are kept here
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedFormat {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: u32,
+    pub format: String,
+}
+
+impl From<CameraFormat> for SavedFormat {
+    fn from(format: CameraFormat) -> Self {
+        Self {
+            width: format.width(),
+            height: format.height(),
+            frame_rate: format.frame_rate(),
+            format: format.format().to_string(),
+        }
+    }
+}
+
+impl SavedFormat {
+    pub fn camera_format(&self) -> Option<CameraFormat> {
+        let frame_format = [
+            FrameFormat::MJPEG,
+            FrameFormat::YUYV,
+            FrameFormat::NV12,
+            FrameFormat::GRAY,
+            FrameFormat::RAWRGB,
+        ]
+        .into_iter()
+        .find(|f| f.to_string() == self.format)?;
+        Some(CameraFormat::new(
+            Resolution::new(self.width, self.height),
+            frame_format,
+            self.frame_rate,
+        ))
+    }
+}
+
+/// Every profile saved for a single camera, along with the one that is applied when it is opened.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraProfiles {
+    #[serde(skip)]
+    pub camera: String,
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl CameraProfiles {
+    pub fn load(camera: &str) -> Self {
+        Self {
+            camera: camera.to_string(),
+            ..load_config(&profiles_file(camera)).unwrap_or_default()
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_config(&profiles_file(&self.camera), self)
+    }
+
+    pub fn default_profile(&self) -> Option<&Profile> {
+        self.profiles.get(self.default.as_ref()?)
+    }
+}
+
+// the text box in the gui that new profiles are named with
+#[derive(Resource, Default)]
+pub struct ProfileName(pub String);
+
+// camera names come straight from the driver, so anything that could upset a file system is replaced
+fn profiles_file(camera: &str) -> String {
+    let name: String = camera
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("profiles/{}.ron", name)
+}
@@ -0,0 +1,620 @@
+// This is for archiving performances, the window output is written out once for every animation tick so
+// that the recording has exactly the cadence that was seen on the platter.
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use bevy::prelude::*;
+use flume::{bounded, unbounded, Receiver, Sender, TrySendError};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+use kira::sound::static_sound::StaticSoundData;
+
+use crate::bluetooth::RotationInterval;
+use crate::capture::{timestamp, CaptureRequests, OutputDestination, CAPTURE_DIR};
+use crate::rotation::{directed_speed, playback_rate, ResponseCurve};
+use crate::zoetrope::{RotationDirection, ZoetropeAnimationThresholdSpeed};
+
+// frames waiting to be encoded, any more than this and they are left out for the last one to be repeated
+const RECORDING_QUEUE: usize = 8;
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    #[default]
+    PngSequence,
+    MjpegAvi,
+}
+
+/// A frame of the window output for the given tick of the recording.
+pub struct RecordingFrame {
+    pub tick: u64,
+    pub image: RgbaImage,
+}
+
+#[derive(Resource, Default)]
+pub struct Recording {
+    pub format: RecordingFormat,
+    active: Option<Recorder>,
+    // stopped recordings that are still being written out, checked on by `record_finish`
+    finishing: Vec<(PathBuf, JoinHandle<Result<()>>)>,
+    pub last_saved: Option<String>,
+}
+
+struct Recorder {
+    dir: PathBuf,
+    frames_tx: Sender<(u64, RgbaImage)>,
+    // the playback rate of the music on every tick, the song is rendered from these as frames are written
+    rates_tx: Sender<(u64, f64)>,
+    writer: JoinHandle<Result<()>>,
+    // fixed ticks since the recording started
+    tick: u64,
+    requested: u64,
+    skipped: u64,
+    period: Duration,
+}
+
+/// The song as it was when the recording started.
+pub struct Soundtrack {
+    pub sound: StaticSoundData,
+    // seconds into the song
+    pub start: f64,
+}
+
+impl Recording {
+    pub fn start(&mut self, period: Duration, soundtrack: Option<Soundtrack>) -> Result<()> {
+        let dir = PathBuf::from(CAPTURE_DIR).join(format!("recording-{}", timestamp()));
+        std::fs::create_dir_all(&dir)?;
+        let soundtrack = soundtrack.filter(|soundtrack| {
+            let empty = soundtrack.sound.frames.is_empty();
+            if empty {
+                warn!("The song has no audio, so the recording is left silent");
+            }
+            !empty
+        });
+        let (frames_tx, frames_rx) = bounded(RECORDING_QUEUE);
+        let (rates_tx, rates_rx) = unbounded();
+        let format = self.format;
+        let writer_dir = dir.clone();
+        let writer = std::thread::spawn(move || {
+            let mut song =
+                soundtrack.map(|soundtrack| SongRenderer::new(soundtrack, period, rates_rx));
+            let sample_rate = song.as_ref().map(|song| song.sample_rate());
+            let mut sink = FrameSink::new(&writer_dir, format, period, sample_rate);
+            let mut last: Option<(u64, Vec<u8>)> = None;
+            for (tick, image) in frames_rx.iter() {
+                let encoded = encode(&image, format)?;
+                // ticks that were never drawn, or whose frame was skipped, repeat the frame before them
+                let size = (image.width(), image.height());
+                if let Some((written, previous)) = &last {
+                    for gap in written + 1..tick {
+                        let audio = song.as_mut().map(|song| song.render(gap));
+                        sink.write(gap, previous, size, audio.as_deref())?;
+                    }
+                }
+                let audio = song.as_mut().map(|song| song.render(tick));
+                sink.write(tick, &encoded, size, audio.as_deref())?;
+                last = Some((tick, encoded));
+            }
+            sink.finish()
+        });
+        self.active = Some(Recorder {
+            dir,
+            frames_tx,
+            rates_tx,
+            writer,
+            tick: 0,
+            requested: 0,
+            skipped: 0,
+            period,
+        });
+        Ok(())
+    }
+
+    // the frames that are still queued are written out in the background, see `record_finish`
+    pub fn stop(&mut self) {
+        let Some(recorder) = self.active.take() else {
+            return;
+        };
+        drop(recorder.frames_tx);
+        drop(recorder.rates_tx);
+        if recorder.skipped > 0 {
+            warn!(
+                "{} frames could not be encoded in time and were repeated",
+                recorder.skipped
+            );
+        }
+        info!("Finishing the recording into {}", recorder.dir.display());
+        self.finishing.push((recorder.dir, recorder.writer));
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Whether a stopped recording is still being written out.
+    pub fn is_finishing(&self) -> bool {
+        !self.finishing.is_empty()
+    }
+
+    /// How many ticks have been recorded so far.
+    pub fn ticks(&self) -> Option<u64> {
+        self.active.as_ref().map(|recorder| recorder.tick)
+    }
+
+    /// How long the recording plays for so far, at the tick rate it was started with.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.active
+            .as_ref()
+            .map(|recorder| recorder.period * recorder.tick as u32)
+    }
+}
+
+// the song played back the way the crank scrubbed it, with each tick as long as a frame of the video
+struct SongRenderer {
+    soundtrack: Soundtrack,
+    rates: Receiver<(u64, f64)>,
+    tick_samples: f64,
+    // where in the song playback is, in its own samples
+    position: f64,
+    // samples played so far, kept as a total so that rounding each tick does not drift
+    played: u64,
+}
+
+impl SongRenderer {
+    fn new(soundtrack: Soundtrack, period: Duration, rates: Receiver<(u64, f64)>) -> Self {
+        let rate = soundtrack.sound.sample_rate as f64;
+        Self {
+            tick_samples: period.as_secs_f64() * rate,
+            position: soundtrack.start * rate,
+            soundtrack,
+            rates,
+            played: 0,
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.soundtrack.sound.sample_rate
+    }
+
+    // 16 bit stereo samples for the tick, the song still moves on through ticks before it that had no frame
+    fn render(&mut self, tick: u64) -> Vec<u8> {
+        let mut samples = Vec::new();
+        // the rate of a tick is sent before its frame is asked for, so it is always there by now
+        while let Ok((at, speed)) = self.rates.recv() {
+            let out = (at == tick).then_some(&mut samples);
+            self.play(at, speed, out);
+            if at >= tick {
+                break;
+            }
+        }
+        samples
+    }
+
+    fn play(&mut self, tick: u64, speed: f64, mut out: Option<&mut Vec<u8>>) {
+        let frames = &self.soundtrack.sound.frames;
+        let end = (self.tick_samples * tick as f64).round() as u64;
+        while self.played < end {
+            if let Some(out) = out.as_mut() {
+                // the song is looped while running, so it is here too
+                let at = self.position.rem_euclid(frames.len() as f64);
+                let first = at.floor() as usize;
+                let (a, b) = (frames[first], frames[(first + 1) % frames.len()]);
+                let t = (at - first as f64) as f32;
+                for sample in [
+                    a.left + (b.left - a.left) * t,
+                    a.right + (b.right - a.right) * t,
+                ] {
+                    let sample = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+                    out.extend(sample.to_le_bytes());
+                }
+            }
+            self.position += speed;
+            self.played += 1;
+        }
+    }
+}
+
+// the format chunk of stereo 16 bit pcm, shared by the wav file and the audio stream of the avi
+fn pcm_format(sample_rate: u32) -> Vec<u8> {
+    let mut format = Vec::with_capacity(16);
+    for value in [1u16, 2] {
+        format.extend(value.to_le_bytes());
+    }
+    for value in [sample_rate, sample_rate * 4] {
+        format.extend(value.to_le_bytes());
+    }
+    for value in [4u16, 16] {
+        format.extend(value.to_le_bytes());
+    }
+    format
+}
+
+fn write_wav(path: &Path, sample_rate: u32, samples: &[u8]) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let data = u32::try_from(samples.len()).map_err(|_| anyhow!("The recording is too long"))?;
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&pcm_format(sample_rate))?;
+    file.write_all(b"data")?;
+    file.write_all(&data.to_le_bytes())?;
+    file.write_all(samples)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn encode(image: &RgbaImage, format: RecordingFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        RecordingFormat::PngSequence => {
+            image.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?
+        }
+        RecordingFormat::MjpegAvi => {
+            // jpeg has no alpha channel
+            let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+            JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).encode_image(&rgb)?
+        }
+    }
+    Ok(bytes)
+}
+
+enum FrameSink {
+    // the song, if there is one, is kept until the end and saved as a wav beside the frames
+    Sequence {
+        dir: PathBuf,
+        sample_rate: Option<u32>,
+        audio: Vec<u8>,
+    },
+    Avi {
+        path: PathBuf,
+        period: Duration,
+        sample_rate: Option<u32>,
+        // made when the first frame arrives, as that is when the size is known
+        writer: Option<AviWriter>,
+    },
+}
+
+impl FrameSink {
+    fn new(
+        dir: &Path,
+        format: RecordingFormat,
+        period: Duration,
+        sample_rate: Option<u32>,
+    ) -> Self {
+        match format {
+            RecordingFormat::PngSequence => Self::Sequence {
+                dir: dir.to_path_buf(),
+                sample_rate,
+                audio: Vec::new(),
+            },
+            RecordingFormat::MjpegAvi => Self::Avi {
+                path: dir.join("recording.avi"),
+                period,
+                sample_rate,
+                writer: None,
+            },
+        }
+    }
+
+    fn write(
+        &mut self,
+        tick: u64,
+        encoded: &[u8],
+        size: (u32, u32),
+        audio: Option<&[u8]>,
+    ) -> Result<()> {
+        match self {
+            Self::Sequence {
+                dir, audio: kept, ..
+            } => {
+                std::fs::write(dir.join(format!("{:06}.png", tick)), encoded)?;
+                kept.extend_from_slice(audio.unwrap_or_default());
+            }
+            Self::Avi {
+                path,
+                period,
+                sample_rate,
+                writer,
+            } => {
+                if writer.is_none() {
+                    *writer = Some(AviWriter::create(path, size, *period, *sample_rate)?);
+                }
+                if let Some(writer) = writer {
+                    writer.write_frame(encoded)?;
+                    // each tick's audio follows its frame, so the two streams stay interleaved
+                    if let Some(audio) = audio {
+                        writer.write_audio(audio)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Sequence {
+                dir,
+                sample_rate: Some(sample_rate),
+                audio,
+            } => write_wav(&dir.join("audio.wav"), sample_rate, &audio),
+            Self::Avi {
+                writer: Some(writer),
+                ..
+            } => writer.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+const VIDEO_CHUNK: &[u8; 4] = b"00dc";
+const AUDIO_CHUNK: &[u8; 4] = b"01wb";
+
+// an MJPEG stream in an AVI, which is what the playback source reads back, along with the song as pcm
+struct AviWriter {
+    file: BufWriter<File>,
+    // where the fields that are only known at the end live
+    total_frames_at: u64,
+    length_at: u64,
+    audio_length_at: Option<u64>,
+    movi_at: u64,
+    // (chunk id, offset from the `movi` fourcc, size) of every chunk for the index
+    index: Vec<(&'static [u8; 4], u32, u32)>,
+    max_frame: u32,
+    // stereo 16 bit samples written so far
+    audio_samples: u64,
+}
+
+impl AviWriter {
+    fn create(
+        path: &Path,
+        (width, height): (u32, u32),
+        period: Duration,
+        sample_rate: Option<u32>,
+    ) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let micros = period.as_micros().max(1) as u32;
+        let (streams, flags, audio_list) = match sample_rate {
+            // has an index, and is interleaved
+            Some(_) => (2, 0x110, 8 + 4 + 8 + 56 + 8 + 16),
+            None => (1, 0x10, 0),
+        };
+        let mut header = Vec::new();
+        header.extend(b"RIFF\0\0\0\0AVI ");
+        header.extend(b"LIST");
+        header.extend(&(4 + 8 + 56 + 8 + 4 + 8 + 56 + 8 + 40u32 + audio_list).to_le_bytes());
+        header.extend(b"hdrl");
+        // main header
+        header.extend(b"avih");
+        header.extend(&56u32.to_le_bytes());
+        let total_frames_at = header.len() as u64 + 16;
+        for value in [
+            micros, 0, 0, flags, 0, 0, streams, 0, width, height, 0, 0, 0, 0,
+        ] {
+            header.extend(&value.to_le_bytes());
+        }
+        header.extend(b"LIST");
+        header.extend(&(4 + 8 + 56 + 8 + 40u32).to_le_bytes());
+        header.extend(b"strl");
+        // stream header, the rate over the scale is the fixed tick rate
+        header.extend(b"strh");
+        header.extend(&56u32.to_le_bytes());
+        header.extend(b"vidsMJPG");
+        for value in [0u32, 0, 0, micros, 1_000_000, 0] {
+            header.extend(&value.to_le_bytes());
+        }
+        let length_at = header.len() as u64;
+        for value in [0u32, 0, u32::MAX, 0] {
+            header.extend(&value.to_le_bytes());
+        }
+        for value in [0u16, 0, width as u16, height as u16] {
+            header.extend(&value.to_le_bytes());
+        }
+        // stream format
+        header.extend(b"strf");
+        header.extend(&40u32.to_le_bytes());
+        header.extend(&40u32.to_le_bytes());
+        header.extend(&width.to_le_bytes());
+        header.extend(&height.to_le_bytes());
+        header.extend(&1u16.to_le_bytes());
+        header.extend(&24u16.to_le_bytes());
+        header.extend(b"MJPG");
+        for value in [width * height * 3, 0, 0, 0, 0] {
+            header.extend(&value.to_le_bytes());
+        }
+        let mut audio_length_at = None;
+        if let Some(sample_rate) = sample_rate {
+            header.extend(b"LIST");
+            header.extend(&(4 + 8 + 56 + 8 + 16u32).to_le_bytes());
+            header.extend(b"strl");
+            header.extend(b"strh");
+            header.extend(&56u32.to_le_bytes());
+            header.extend(b"auds\0\0\0\0");
+            // counted in blocks of one stereo sample
+            for value in [0u32, 0, 0, 4, sample_rate * 4, 0] {
+                header.extend(&value.to_le_bytes());
+            }
+            audio_length_at = Some(header.len() as u64);
+            for value in [0u32, 0, u32::MAX, 4] {
+                header.extend(&value.to_le_bytes());
+            }
+            header.extend(&[0; 8]);
+            header.extend(b"strf");
+            header.extend(&16u32.to_le_bytes());
+            header.extend(pcm_format(sample_rate));
+        }
+        let movi_at = header.len() as u64;
+        header.extend(b"LIST\0\0\0\0movi");
+        file.write_all(&header)?;
+        Ok(Self {
+            file,
+            total_frames_at,
+            length_at,
+            audio_length_at,
+            movi_at,
+            index: Vec::new(),
+            max_frame: 0,
+            audio_samples: 0,
+        })
+    }
+
+    fn write_chunk(&mut self, id: &'static [u8; 4], data: &[u8]) -> Result<()> {
+        let at = self.file.stream_position()? - (self.movi_at + 8);
+        let size = data.len() as u32;
+        self.file.write_all(id)?;
+        self.file.write_all(&size.to_le_bytes())?;
+        self.file.write_all(data)?;
+        // chunks are padded to an even length
+        if size & 1 == 1 {
+            self.file.write_all(&[0])?;
+        }
+        self.index.push((id, at as u32, size));
+        Ok(())
+    }
+
+    fn write_frame(&mut self, jpeg: &[u8]) -> Result<()> {
+        self.write_chunk(VIDEO_CHUNK, jpeg)?;
+        self.max_frame = self.max_frame.max(jpeg.len() as u32);
+        Ok(())
+    }
+
+    fn write_audio(&mut self, samples: &[u8]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        self.write_chunk(AUDIO_CHUNK, samples)?;
+        self.audio_samples += samples.len() as u64 / 4;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        let movi_end = self.file.stream_position()?;
+        self.file.write_all(b"idx1")?;
+        self.file
+            .write_all(&(self.index.len() as u32 * 16).to_le_bytes())?;
+        for (id, offset, size) in self.index.iter() {
+            self.file.write_all(*id)?;
+            // every frame of MJPEG is a key frame, as is every block of pcm
+            self.file.write_all(&0x10u32.to_le_bytes())?;
+            self.file.write_all(&offset.to_le_bytes())?;
+            self.file.write_all(&size.to_le_bytes())?;
+        }
+        let end = self.file.stream_position()?;
+        let frames = self
+            .index
+            .iter()
+            .filter(|(id, ..)| *id == VIDEO_CHUNK)
+            .count() as u64;
+        let mut sizes = vec![
+            (4, end - 8),
+            (self.movi_at + 4, movi_end - self.movi_at - 8),
+            (self.total_frames_at, frames),
+            (self.length_at, frames),
+            (self.length_at + 4, self.max_frame as u64),
+        ];
+        if let Some(at) = self.audio_length_at {
+            sizes.push((at, self.audio_samples));
+        }
+        for (at, value) in sizes {
+            let value = u32::try_from(value).map_err(|_| anyhow!("The recording is too long"))?;
+            self.file.seek(SeekFrom::Start(at))?;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+// runs on the fixed tick right after the platter is turned
+pub fn record_tick(
+    mut recording: ResMut<Recording>,
+    rotation: Res<RotationInterval>,
+    max: Res<ZoetropeAnimationThresholdSpeed>,
+    dir: Res<RotationDirection>,
+    curve: Res<ResponseCurve>,
+) {
+    let Some(recorder) = recording.active.as_mut() else {
+        return;
+    };
+    recorder.tick += 1;
+    // the same rate that `audio_modulation_rotation` plays the music at
+    let speed = directed_speed(rotation.0, max.0, dir.audio, &curve);
+    let _ = recorder
+        .rates_tx
+        .send((recorder.tick, playback_rate(speed)));
+}
+
+// asks for the window output once for every tick that has happened since the last frame
+pub fn record_request(mut recording: ResMut<Recording>, mut requests: ResMut<CaptureRequests>) {
+    let Some(recorder) = recording.active.as_mut() else {
+        return;
+    };
+    if recorder.tick > recorder.requested {
+        recorder.requested = recorder.tick;
+        requests.request_output(OutputDestination::Recording(recorder.tick));
+    }
+}
+
+pub fn record_frames(mut recording: ResMut<Recording>, mut frames: EventReader<RecordingFrame>) {
+    let Some(recorder) = recording.active.as_mut() else {
+        frames.clear();
+        return;
+    };
+    for frame in frames.iter() {
+        match recorder
+            .frames_tx
+            .try_send((frame.tick, frame.image.clone()))
+        {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => recorder.skipped += 1,
+            Err(TrySendError::Disconnected(_)) => {
+                error!("The recording stopped unexpectedly");
+                break;
+            }
+        }
+    }
+}
+
+// reports on stopped recordings once they are written out, rather than holding up the display for them
+pub fn record_finish(mut recording: ResMut<Recording>) {
+    if !recording
+        .finishing
+        .iter()
+        .any(|(_, writer)| writer.is_finished())
+    {
+        return;
+    }
+    let (done, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut recording.finishing)
+        .into_iter()
+        .partition(|(_, writer)| writer.is_finished());
+    recording.finishing = pending;
+    for (dir, writer) in done {
+        match writer.join() {
+            Ok(Ok(())) => info!("Finished recording into {}", dir.display()),
+            Ok(Err(e)) => error!("Recording into {} failed: {}", dir.display(), e),
+            Err(_) => error!("The recording thread panicked"),
+        }
+        recording.last_saved = Some(dir.display().to_string());
+    }
+}
+
+// the recording is finished off when going back to the setup menu, so that it is not left half written
+pub fn record_teardown(mut recording: ResMut<Recording>) {
+    recording.stop();
+}
+
+/// Starts the recording with the current tick rate and song.
+pub fn start_recording(
+    recording: &mut Recording,
+    fixed_time: &FixedTime,
+    soundtrack: Option<Soundtrack>,
+) {
+    if let Err(e) = recording.start(fixed_time.period, soundtrack) {
+        error!("Could not start recording: {}", e);
+    }
+}
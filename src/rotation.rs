@@ -0,0 +1,404 @@
+// This is for turning the reading from the crank into how far the platter turns and how fast the music
+// plays, so that the animation and the audio always agree on how fast and which way things are going.
+use std::f32::consts::PI;
+use std::f64::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::zoetrope::Direction;
+
+/// How fast the crank is turning as a fraction of full speed, from -1 to 1. Anything at or past the
+/// threshold either way is full speed.
+pub fn crank_speed(crank: i8, threshold: i8) -> f32 {
+    // a threshold below 1 would divide by zero or flip the sign
+    let threshold = threshold.max(1) as f32;
+    (crank as f32 / threshold).clamp(-1., 1.)
+}
+
+/// How the crank speed is shaped before it is used, so that slow cranking can be made easier to control.
+/// Every curve goes from 0 to 1 and is mirrored for turning the other way.
+#[derive(Resource, Debug, Clone, PartialEq, Default)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    // higher powers spend more of the crank's range on slow speeds
+    Exponential(f32),
+    // eases in and out of full speed
+    SCurve,
+    // ignores the crank below this fraction of full speed, then goes up linearly from there
+    DeadZone(f32),
+    // points between (0, 0) and (1, 1) that are joined with straight lines
+    Piecewise(Vec<(f32, f32)>),
+}
+
+impl ResponseCurve {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Exponential(_) => "Exponential",
+            Self::SCurve => "S-Curve",
+            Self::DeadZone(_) => "Dead Zone",
+            Self::Piecewise(_) => "Custom",
+        }
+    }
+
+    /// One of each kind of curve with its settings at a reasonable starting point.
+    pub fn defaults() -> [ResponseCurve; 5] {
+        [
+            Self::Linear,
+            Self::Exponential(2.),
+            Self::SCurve,
+            Self::DeadZone(0.1),
+            Self::Piecewise(vec![(0.25, 0.1), (0.5, 0.3), (0.75, 0.6)]),
+        ]
+    }
+
+    pub fn apply(&self, speed: f32) -> f32 {
+        let x = speed.abs().min(1.);
+        let y = match self {
+            Self::Linear => x,
+            Self::Exponential(power) => x.powf(power.max(f32::EPSILON)),
+            Self::SCurve => x * x * (3. - 2. * x),
+            Self::DeadZone(width) => {
+                let width = width.clamp(0., 0.99);
+                ((x - width) / (1. - width)).max(0.)
+            }
+            Self::Piecewise(points) => piecewise(points, x),
+        };
+        y.clamp(0., 1.).copysign(speed)
+    }
+}
+
+// the points are kept in whatever order they were edited in, so they are sorted here. the ends are fixed,
+// and outputs are carried up from the point before so that the curve never slows down as the crank speeds up
+fn piecewise(points: &[(f32, f32)], x: f32) -> f32 {
+    let mut knots: Vec<(f32, f32)> = points
+        .iter()
+        .filter(|(px, py)| *px > 0. && *px < 1. && py.is_finite())
+        .map(|&(px, py)| (px, py.clamp(0., 1.)))
+        .collect();
+    knots.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut last = (0., 0.);
+    for (px, py) in knots.into_iter().chain([(1., 1.)]) {
+        let py = py.max(last.1);
+        if x <= px {
+            if px - last.0 <= f32::EPSILON {
+                return py;
+            }
+            return last.1 + (py - last.1) * (x - last.0) / (px - last.0);
+        }
+        last = (px, py);
+    }
+    1.
+}
+
+/// The crank speed after shaping, turned to suit the direction that was picked in the gui.
+pub fn directed_speed(crank: i8, threshold: i8, dir: Direction, curve: &ResponseCurve) -> f32 {
+    dir * curve.apply(crank_speed(crank, threshold))
+}
+
+/// How far the platter turns for a slice, in radians. Full speed is one slice per tick.
+pub fn slice_angle(slices: u8) -> f32 {
+    2. * PI / slices.max(1) as f32
+}
+
+/// The playback rate of the music at this speed, negative plays it backwards.
+pub fn playback_rate(speed: f32) -> f64 {
+    speed as f64
+}
+
+/// Where the platter is, kept apart from its transform so that it can be lined up with the sculpture.
+/// It is counted in turns rather than by adding up small rotations, so that hours of cranking do not
+/// leave it a little off from where the slices are.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlatterPhase {
+    // how far the crank has turned the platter since the reference, as a fraction of a turn
+    turns: f64,
+    // added on top of the turning, in radians, to line the platter up with the sculpture
+    pub offset: f32,
+    // only ever show the platter at whole slices from the reference
+    pub snap: bool,
+    // slices the crank turned it by on the last tick
+    last_step: f32,
+}
+
+impl PlatterPhase {
+    /// Turns the platter by `speed` slices.
+    pub fn advance(&mut self, speed: f32, slices: u8) {
+        self.turns = (self.turns + speed as f64 / slices.max(1) as f64).rem_euclid(1.);
+        self.last_step = speed;
+    }
+
+    /// How many slices the crank turned the platter by on the last tick, leaving out nudges and snapping.
+    pub fn last_step(&self) -> f32 {
+        self.last_step
+    }
+
+    /// How far the platter is turned from where it was set up, in radians.
+    pub fn displayed(&self, slices: u8) -> f32 {
+        let slices = slices.max(1) as f64;
+        let turns = if self.snap {
+            (self.turns * slices).round() / slices
+        } else {
+            self.turns
+        };
+        (turns * TAU + self.offset as f64).rem_euclid(TAU) as f32
+    }
+
+    /// The slice the reference is at, counting the way the platter turns.
+    pub fn slice(&self, slices: u8) -> u8 {
+        let slices = slices.max(1) as u32;
+        ((self.turns * slices as f64).round() as u32 % slices) as u8
+    }
+
+    /// Makes wherever the platter is now slice 0, without moving it.
+    pub fn set_reference(&mut self, slices: u8) {
+        self.offset = self.displayed(slices);
+        self.turns = 0.;
+    }
+
+    /// Moves the platter by this many radians without changing which slice it is on.
+    pub fn nudge(&mut self, by: f32) {
+        self.offset = (self.offset + by).rem_euclid(2. * PI);
+    }
+
+    /// Back to how it was set up.
+    pub fn reset(&mut self) {
+        *self = Self {
+            snap: self.snap,
+            ..default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: std::ops::RangeInclusive<i8> = 1..=i8::MAX;
+
+    #[test]
+    fn speed_is_bounded() {
+        for threshold in THRESHOLDS {
+            for crank in i8::MIN..=i8::MAX {
+                let speed = crank_speed(crank, threshold);
+                assert!((-1. ..=1.).contains(&speed), "{} at {}", crank, threshold);
+            }
+        }
+    }
+
+    #[test]
+    fn speed_is_symmetric() {
+        for threshold in THRESHOLDS {
+            // -128 has no positive counterpart
+            for crank in -i8::MAX..=i8::MAX {
+                assert_eq!(
+                    crank_speed(-crank, threshold),
+                    -crank_speed(crank, threshold),
+                    "{} at {}",
+                    crank,
+                    threshold
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn speed_is_monotonic() {
+        for threshold in THRESHOLDS {
+            for crank in i8::MIN..i8::MAX {
+                assert!(
+                    crank_speed(crank, threshold) <= crank_speed(crank + 1, threshold),
+                    "{} at {}",
+                    crank,
+                    threshold
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn speed_saturates_at_the_threshold() {
+        for threshold in THRESHOLDS {
+            for crank in threshold..=i8::MAX {
+                assert_eq!(crank_speed(crank, threshold), 1.);
+                assert_eq!(crank_speed(-crank, threshold), -1.);
+            }
+            assert_eq!(crank_speed(i8::MIN, threshold), -1.);
+        }
+    }
+
+    #[test]
+    fn still_crank_is_still() {
+        for threshold in i8::MIN..=i8::MAX {
+            assert_eq!(crank_speed(0, threshold), 0.);
+        }
+    }
+
+    #[test]
+    fn bad_thresholds_act_like_one() {
+        for threshold in i8::MIN..=1 {
+            for crank in i8::MIN..=i8::MAX {
+                assert_eq!(crank_speed(crank, threshold), crank_speed(crank, 1));
+            }
+        }
+    }
+
+    #[test]
+    fn direction_flips_the_sign() {
+        for threshold in THRESHOLDS {
+            for crank in i8::MIN..=i8::MAX {
+                let cw = directed_speed(crank, threshold, Direction::CW, &ResponseCurve::Linear);
+                let ccw = directed_speed(crank, threshold, Direction::CCW, &ResponseCurve::Linear);
+                assert_eq!(cw, crank_speed(crank, threshold));
+                assert_eq!(ccw, -cw);
+            }
+        }
+    }
+
+    fn curves() -> Vec<ResponseCurve> {
+        let mut curves = ResponseCurve::defaults().to_vec();
+        curves.extend([
+            ResponseCurve::Exponential(0.5),
+            ResponseCurve::Exponential(4.),
+            ResponseCurve::DeadZone(0.),
+            ResponseCurve::DeadZone(0.5),
+            // out of order, dipping and out of range points still make a usable curve
+            ResponseCurve::Piecewise(vec![(0.8, 0.2), (0.3, 0.5), (1.5, -1.)]),
+            ResponseCurve::Piecewise(vec![(0.5, 0.5), (0.5, 0.9)]),
+            ResponseCurve::Piecewise(vec![]),
+        ]);
+        curves
+    }
+
+    #[test]
+    fn curves_keep_their_ends() {
+        for curve in curves() {
+            assert_eq!(curve.apply(0.), 0., "{:?}", curve);
+            assert_eq!(curve.apply(1.), 1., "{:?}", curve);
+            assert_eq!(curve.apply(-1.), -1., "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_are_symmetric_monotonic_and_bounded() {
+        for curve in curves() {
+            for threshold in [1, 5, 20, i8::MAX] {
+                let mut last = -1.;
+                for crank in i8::MIN..=i8::MAX {
+                    let speed = directed_speed(crank, threshold, Direction::CW, &curve);
+                    assert!((-1. ..=1.).contains(&speed), "{:?} {}", curve, crank);
+                    assert!(speed >= last, "{:?} {} at {}", curve, crank, threshold);
+                    last = speed;
+                    if crank != i8::MIN {
+                        let mirrored = directed_speed(-crank, threshold, Direction::CW, &curve);
+                        assert_eq!(mirrored, -speed, "{:?} {}", curve, crank);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dead_zone_ignores_slow_cranking() {
+        let curve = ResponseCurve::DeadZone(0.5);
+        for crank in -2..=2 {
+            assert_eq!(directed_speed(crank, 5, Direction::CW, &curve), 0.);
+        }
+        assert_eq!(directed_speed(5, 5, Direction::CW, &curve), 1.);
+    }
+
+    // how many slices the phase is turned from where it was set up, either way
+    fn turned(phase: &PlatterPhase, slices: u8) -> f32 {
+        let angle = phase.displayed(slices);
+        let angle = if angle > PI { angle - 2. * PI } else { angle };
+        angle / slice_angle(slices)
+    }
+
+    #[test]
+    fn audio_follows_the_animation() {
+        for crank in i8::MIN..=i8::MAX {
+            let speed = directed_speed(crank, 5, Direction::CW, &ResponseCurve::SCurve);
+            let mut phase = PlatterPhase::default();
+            phase.advance(speed, 24);
+            // both go the same way, and a full slice per tick is normal speed
+            let rate = playback_rate(speed);
+            assert!((turned(&phase, 24) - rate as f32).abs() < 1e-4, "{}", crank);
+        }
+    }
+
+    #[test]
+    fn full_speed_is_a_slice_per_tick() {
+        for slices in 1..=u8::MAX {
+            let mut phase = PlatterPhase::default();
+            phase.advance(1., slices);
+            assert_eq!(phase.slice(slices), 1 % slices);
+            assert!((slice_angle(slices) * slices as f32 - 2. * PI).abs() < 1e-4);
+        }
+        // no slices is treated as one rather than dividing by zero
+        assert_eq!(slice_angle(0), 2. * PI);
+    }
+
+    #[test]
+    fn phase_does_not_drift() {
+        let mut phase = PlatterPhase::default();
+        // a little over a day of cranking at full speed, then back again at an awkward one
+        for _ in 0..24 * 100_000 {
+            phase.advance(1., 24);
+        }
+        for _ in 0..24 * 100_000 {
+            phase.advance(-0.3, 24);
+        }
+        for _ in 0..7 * 100_000 {
+            phase.advance(0.3, 24);
+        }
+        for _ in 0..17 * 100_000 {
+            phase.advance(0.3, 24);
+        }
+        assert!(turned(&phase, 24).abs() < 1e-4);
+        assert_eq!(phase.slice(24), 0);
+    }
+
+    #[test]
+    fn phase_snaps_to_whole_slices() {
+        let mut phase = PlatterPhase {
+            snap: true,
+            ..Default::default()
+        };
+        phase.advance(0.4, 24);
+        assert_eq!(phase.displayed(24), 0.);
+        phase.advance(0.4, 24);
+        assert_eq!(turned(&phase, 24), 1.);
+        assert_eq!(phase.slice(24), 1);
+        // turning back past the reference goes round to the last slice
+        phase.advance(-2., 24);
+        assert_eq!(phase.slice(24), 23);
+        assert!((turned(&phase, 24) + 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn reference_keeps_the_platter_still() {
+        let mut phase = PlatterPhase::default();
+        phase.advance(5.3, 24);
+        let before = phase.displayed(24);
+        phase.set_reference(24);
+        assert!((phase.displayed(24) - before).abs() < 1e-6);
+        assert_eq!(phase.slice(24), 0);
+        phase.advance(1., 24);
+        assert_eq!(phase.slice(24), 1);
+        assert!((phase.displayed(24) - before - slice_angle(24)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn nudging_moves_without_changing_slice() {
+        let mut phase = PlatterPhase::default();
+        phase.advance(3., 24);
+        phase.nudge(-0.01);
+        assert_eq!(phase.slice(24), 3);
+        // only the crank counts as the platter having turned
+        assert_eq!(phase.last_step(), 3.);
+        assert!((phase.displayed(24) - (3. * slice_angle(24) - 0.01)).abs() < 1e-6);
+        phase.reset();
+        assert_eq!(phase.displayed(24), 0.);
+    }
+}
@@ -1,18 +1,33 @@
 use crate::audio::Song;
 use crate::bluetooth::ArduinoConnected;
-use crate::camera::hash_available_cameras;
+use crate::camera::{
+    describe_format, hash_available_cameras, query_camera_formats, PixelLayout, VideoSource,
+};
+use crate::playback::available_playbacks;
+use crate::profile::CameraProfiles;
 use crate::zoetrope::Slices;
 use bevy::prelude::*;
 use bevy::window::{PresentMode, WindowMode};
 use bevy_egui::{egui, EguiContexts, EguiSettings};
 use egui::{FontFamily, FontId, RichText};
+use nokhwa::utils::CameraFormat;
 
 #[allow(unused_imports)]
 use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter};
 #[allow(unused_imports)]
 use btleplug::platform::{Adapter, Manager, Peripheral};
 
-#[derive(Resource, Default, PartialEq)]
+// the formats of the selected camera, only queried again when a different camera is picked as opening
+// the device is slow
+#[derive(Resource, Default)]
+pub struct CameraFormats {
+    pub camera: Option<u32>,
+    pub formats: Vec<CameraFormat>,
+}
+
+/// The quality the zoetrope is aiming for. Camera formats that a square this big can be cropped out of are
+/// marked in the setup menu.
+#[derive(Resource, Default, PartialEq, Clone, Copy)]
 pub enum Resolutions {
     #[default]
     Fourk,
@@ -21,13 +36,24 @@ pub enum Resolutions {
 }
 
 impl Resolutions {
-    fn as_str(&self) -> &str {
+    pub const ALL: [Self; 3] = [Self::Fourk, Self::TenEighty, Self::FourteenFourty];
+
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Fourk => "4k30",
             Self::TenEighty => "1080p60",
             Self::FourteenFourty => "1440p60(4:3)",
         }
     }
+
+    /// The side of the biggest square in a frame of this resolution.
+    pub fn square_side(&self) -> u32 {
+        match self {
+            Self::Fourk => 2160,
+            Self::TenEighty => 1080,
+            Self::FourteenFourty => 1440,
+        }
+    }
 }
 
 #[derive(Resource)]
@@ -35,9 +61,8 @@ pub struct StringBuffer(pub String);
 
 #[derive(Resource, Debug)]
 pub struct Settings {
-    pub camera: nokhwa::utils::CameraIndex,
-    pub resolution: nokhwa::utils::Resolution,
-    pub frame_rate: u32,
+    pub source: Option<VideoSource>,
+    pub format: Option<CameraFormat>,
     pub arduino_connection: bool,
     pub song: Option<String>,
 }
@@ -60,7 +85,8 @@ pub fn cleanup_menu(mut windows: Query<&mut Window>) {
 
 pub fn setup_menu(
     mut ctx: EguiContexts,
-    mut quality: ResMut<Resolutions>,
+    mut formats: ResMut<CameraFormats>,
+    mut target: ResMut<Resolutions>,
     mut song: ResMut<Song>,
     arduino: Res<ArduinoConnected>,
     mut next_state: ResMut<NextState<RunningStates>>,
@@ -72,7 +98,49 @@ pub fn setup_menu(
 ) {
     let mut window = windows.single_mut();
     window.decorations = false;
-    let (mut selected, cameras) = hash_available_cameras();
+    let (selected, cameras) = hash_available_cameras();
+    // default to the first camera found, but keep whatever was picked on the previous frames
+    if settings.source.is_none() {
+        settings.source = selected.map(|(name, ind)| VideoSource::Camera(name, ind));
+    }
+    let camera = match settings.source {
+        Some(VideoSource::Camera(_, ind)) => Some(ind),
+        _ => None,
+    };
+    if formats.camera != camera {
+        formats.camera = camera;
+        formats.formats = camera
+            .map(|ind| {
+                query_camera_formats(ind).unwrap_or_else(|e| {
+                    warn!("Could not query the formats of the camera: {}", e);
+                    Vec::new()
+                })
+            })
+            .unwrap_or_default();
+        // a camera with a default profile starts out on the format that was saved with it
+        let saved = match &settings.source {
+            Some(source) => CameraProfiles::load(&source.name())
+                .default_profile()
+                .and_then(|profile| profile.format.as_ref()?.camera_format()),
+            None => None,
+        };
+        settings.format = saved
+            .filter(|format| formats.formats.contains(format))
+            .or_else(|| formats.formats.first().copied());
+    }
+    // a format is marked when the square cut from it is at least as big as the target
+    let side = target.square_side();
+    let label = |format: &CameraFormat| {
+        let mut label = describe_format(format);
+        if format.width().min(format.height()) >= side {
+            label.push_str(" (square-croppable)");
+        }
+        // these skip the cpu decode, which matters most at the higher resolutions
+        if PixelLayout::of(format.format()) != PixelLayout::Rgba {
+            label.push_str(" (GPU converted)");
+        }
+        label
+    };
     egui::CentralPanel::default().show(ctx.ctx_mut(), |ui| {
         // ctx.set_pixels_per_point(5.0);
         // ui.style_mut().override_text_style = Some(egui::TextStyle::Body);
@@ -99,48 +167,66 @@ pub fn setup_menu(
                 egui::ComboBox::from_label(
                     "Select the camera that will be used to capture the video feed",
                 )
-                .selected_text(format!(
-                    "{}",
-                    selected
-                        .clone()
-                        .unwrap_or(("No Camera".to_string(), u32::MAX))
-                        .0
-                ))
+                .selected_text(
+                    settings
+                        .source
+                        .as_ref()
+                        .map_or("No Camera".to_string(), VideoSource::name),
+                )
                 .show_ui(ui, |ui| {
                     ui.style_mut().wrap = Some(false);
                     ui.set_min_width(50.0);
                     // this makes a new item for each camera that was found
                     for (name, ind) in cameras.iter() {
-                        ui.selectable_value(&mut selected, Some((name.to_string(), *ind)), name);
+                        ui.selectable_value(
+                            &mut settings.source,
+                            Some(VideoSource::Camera(name.to_string(), *ind)),
+                            name,
+                        );
+                    }
+                    // the built in generated source, for when there is no camera to use
+                    ui.selectable_value(
+                        &mut settings.source,
+                        Some(VideoSource::TestPattern),
+                        VideoSource::TestPattern.name(),
+                    );
+                    // pre-rendered loops that can be played in place of the camera
+                    for path in available_playbacks() {
+                        let source = VideoSource::Playback(path);
+                        let name = source.name();
+                        ui.selectable_value(&mut settings.source, Some(source), name);
                     }
                 });
                 ui.end_row();
 
-                // this is for setting the resolutions
-                ui.add(egui::Label::new("Quality"));
+                // the size the formats below are marked against
+                ui.add(egui::Label::new("Target"));
                 egui::ComboBox::from_label(
-                    "Select the Quality of the video feed, in combined Resolution and Frame Rate",
+                    "Select the quality to aim for, formats that can be cropped square at it are marked",
                 )
-                .selected_text(quality.as_str())
+                .selected_text(target.as_str())
                 .show_ui(ui, |ui| {
-                    ui.style_mut().wrap = Some(false);
-                    ui.set_min_width(50.0);
-                    // this is all settings for resolutions
-                    ui.selectable_value(
-                        &mut *quality,
-                        Resolutions::Fourk,
-                        Resolutions::Fourk.as_str(),
-                    );
-                    ui.selectable_value(
-                        &mut *quality,
-                        Resolutions::TenEighty,
-                        Resolutions::TenEighty.as_str(),
-                    );
-                    ui.selectable_value(
-                        &mut *quality,
-                        Resolutions::FourteenFourty,
-                        Resolutions::FourteenFourty.as_str(),
-                    );
+                    for option in Resolutions::ALL {
+                        ui.selectable_value(&mut *target, option, option.as_str());
+                    }
+                });
+                ui.end_row();
+
+                // this is for setting the resolutions
+                ui.add(egui::Label::new("Quality"));
+                ui.add_enabled_ui(!formats.formats.is_empty(), |ui| {
+                    egui::ComboBox::from_label(
+                        "Select the Quality of the video feed, in combined Resolution, Frame Rate and Format",
+                    )
+                    .selected_text(settings.format.as_ref().map_or("Default".to_string(), label))
+                    .show_ui(ui, |ui| {
+                        ui.style_mut().wrap = Some(false);
+                        ui.set_min_width(50.0);
+                        // one entry for everything the camera reports that it can do
+                        for format in formats.formats.iter() {
+                            ui.selectable_value(&mut settings.format, Some(*format), label(format));
+                        }
+                    });
                 });
                 ui.end_row();
 
@@ -194,16 +280,23 @@ pub fn setup_menu(
                 );
             });
 
+        // the test pattern and playback are there for machines without the hardware, so they don't wait
+        // on the crank
+        let needs_crank = matches!(settings.source, Some(VideoSource::Camera(..)));
         // this is where the settings are converted to nokhwa settings
         if ui
             .add_enabled(
-                arduino.0 && selected.is_some(),
+                settings.source.is_some() && (arduino.0 || !needs_crank),
                 egui::Button::new("Continue").min_size([120., 40.].into()),
             )
             .clicked()
         {
-            settings.camera = nokhwa::utils::CameraIndex::Index(selected.clone().unwrap().1);
             match str_buffer.0.parse::<u8>() {
+                Ok(0) => {
+                    warn!("There has to be at least one slice");
+                    info!("Falling back to 24 slices");
+                    slices.0 = 24;
+                }
                 Ok(x) => slices.0 = x,
                 Err(e) => {
                     warn!("Error parsing the input value for the slices: {}", e);
@@ -211,11 +304,6 @@ pub fn setup_menu(
                     slices.0 = 24;
                 }
             }
-            (settings.resolution, settings.frame_rate) = match *quality {
-                Resolutions::Fourk => (nokhwa::utils::Resolution::new(2880, 2160), 30),
-                Resolutions::TenEighty => (nokhwa::utils::Resolution::new(1920, 1080), 60),
-                Resolutions::FourteenFourty => (nokhwa::utils::Resolution::new(1920, 1440), 60),
-            };
             settings.arduino_connection = arduino.0;
             settings.song = match song.0.as_str() {
                 "None" => None,
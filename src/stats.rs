@@ -0,0 +1,132 @@
+// This is for keeping track of how well frames are making it from the source to the display.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy::prelude::*;
+
+use crate::camera::VideoStream;
+use crate::material::FrameUpload;
+
+pub const CAPTURE_DECODE_TIME: DiagnosticId =
+    DiagnosticId::from_u128(147963529101586744213096471530311265611);
+pub const CAPTURE_UPLOAD_TIME: DiagnosticId =
+    DiagnosticId::from_u128(230195842106755930843914770618233408243);
+pub const CAPTURE_LATENCY: DiagnosticId =
+    DiagnosticId::from_u128(60857148553297145361937823840521960517);
+pub const CAPTURE_DROPPED: DiagnosticId =
+    DiagnosticId::from_u128(318452651203988561117906403719844062941);
+
+// how many samples the rolling timings are taken over
+const ROLLING_SAMPLES: usize = 60;
+
+/// Counted on the thread running the source, so they are shared with the main thread.
+#[derive(Debug, Default)]
+pub struct CaptureCounters {
+    // every frame grabbed from the source, counted before it is decoded
+    pub captured: AtomicU64,
+    pub decoded: AtomicU64,
+    // decoded frames that were replaced by a newer one before they could be shown
+    pub dropped: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct Rolling(VecDeque<f64>);
+
+impl Rolling {
+    pub fn push(&mut self, sample: f64) {
+        if self.0.len() == ROLLING_SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(sample);
+    }
+
+    /// The mean of the samples in the window, if there are any.
+    pub fn mean(&self) -> Option<f64> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(self.0.iter().sum::<f64>() / self.0.len() as f64)
+    }
+}
+
+/// How many frames have made it through each step of the pipeline for the current source, along with
+/// how long the steps are taking in milliseconds.
+#[derive(Resource, Debug, Default)]
+pub struct CaptureStats {
+    pub captured: u64,
+    pub decoded: u64,
+    // decoded frames that were replaced by a newer one before they could be shown
+    pub dropped: u64,
+    pub displayed: u64,
+    pub decode_ms: Rolling,
+    // writing the frame into the texture on the gpu
+    pub upload_ms: Rolling,
+    // time from the frame being captured to it being put on the zoetrope
+    pub latency_ms: Rolling,
+}
+
+impl CaptureStats {
+    pub fn record_displayed(&mut self, captured_at: Instant, decode: Duration) {
+        self.displayed += 1;
+        self.decode_ms.push(decode.as_secs_f64() * 1000.);
+        self.latency_ms
+            .push(captured_at.elapsed().as_secs_f64() * 1000.);
+    }
+
+    pub fn record_upload(&mut self, upload: Duration) {
+        self.upload_ms.push(upload.as_secs_f64() * 1000.);
+    }
+
+    pub fn sync_counters(&mut self, counters: &CaptureCounters) {
+        self.captured = counters.captured.load(Ordering::Relaxed);
+        self.decoded = counters.decoded.load(Ordering::Relaxed);
+        self.dropped = counters.dropped.load(Ordering::Relaxed);
+    }
+}
+
+pub fn capture_diagnostics_setup(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics
+        .add(Diagnostic::new(CAPTURE_DECODE_TIME, "capture_decode_time", 20).with_suffix("ms"));
+    diagnostics
+        .add(Diagnostic::new(CAPTURE_UPLOAD_TIME, "capture_upload_time", 20).with_suffix("ms"));
+    diagnostics.add(Diagnostic::new(CAPTURE_LATENCY, "capture_latency", 20).with_suffix("ms"));
+    diagnostics.add(
+        Diagnostic::new(CAPTURE_DROPPED, "capture_dropped_frames", 1).with_smoothing_factor(0.0),
+    );
+}
+
+pub fn capture_diagnostics(
+    mut diagnostics: ResMut<Diagnostics>,
+    mut stats: ResMut<CaptureStats>,
+    cam_query: Query<&VideoStream>,
+    upload: Res<FrameUpload>,
+    mut last_displayed: Local<u64>,
+) {
+    for cam in cam_query.iter() {
+        stats.sync_counters(&cam.counters);
+    }
+    let mut uploaded = false;
+    for time in upload.upload_times() {
+        stats.record_upload(time);
+        uploaded = true;
+    }
+    if uploaded {
+        if let Some(upload) = stats.upload_ms.mean() {
+            diagnostics.add_measurement(CAPTURE_UPLOAD_TIME, || upload);
+        }
+    }
+    // only measured when a new frame was shown, so the same one is not counted twice
+    if stats.displayed != *last_displayed {
+        *last_displayed = stats.displayed;
+        if let Some(decode) = stats.decode_ms.mean() {
+            diagnostics.add_measurement(CAPTURE_DECODE_TIME, || decode);
+        }
+        if let Some(latency) = stats.latency_ms.mean() {
+            diagnostics.add_measurement(CAPTURE_LATENCY, || latency);
+        }
+    }
+    let dropped = stats.dropped as f64;
+    diagnostics.add_measurement(CAPTURE_DROPPED, || dropped);
+}
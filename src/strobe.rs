@@ -0,0 +1,86 @@
+// This is for blanking the display between slice steps, like the slits of a physical zoetrope do, so that
+// each step is seen as a still instead of the platter being seen in motion.
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+
+use crate::capture::WINDOW_ONLY_LAYER;
+use crate::zoetrope::TickMode;
+
+// far bigger than the view at any preset or zoom
+const SHUTTER_SIZE: f32 = 100_000.;
+
+/// How the display is blanked, in fractions of a tick.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Strobe {
+    pub enabled: bool,
+    // how much of each tick the platter is shown for
+    pub duty: f32,
+    // how far into each tick the platter starts being shown
+    pub phase: f32,
+}
+
+impl Default for Strobe {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duty: 0.5,
+            phase: 0.,
+        }
+    }
+}
+
+impl Strobe {
+    /// Whether the platter is shown at this point of a tick, from 0 right as the platter steps to 1 as it
+    /// is about to step again.
+    pub fn is_open(&self, progress: f32) -> bool {
+        !self.enabled || (progress - self.phase).rem_euclid(1.) < self.duty
+    }
+}
+
+/// Covers everything but the crosshair while the strobe is shut.
+#[derive(Component)]
+pub struct StrobeShutter;
+
+pub fn strobe_setup(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::splat(SHUTTER_SIZE)),
+                ..default()
+            },
+            // above the platter but below the crosshair
+            transform: Transform::from_xyz(0., 0., 4.),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        // the captures are of what is on the platter, so they never catch the shutter closed
+        .insert(RenderLayers::layer(WINDOW_ONLY_LAYER))
+        .insert(StrobeShutter);
+}
+
+pub fn strobe_teardown(mut commands: Commands, query: Query<Entity, With<StrobeShutter>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// the time left over from the fixed tick says how far along the current tick is, so this stays in step
+// with `zoetrope_animation` without keeping time of its own. ticking every frame leaves nothing over, so
+// there is no part of a tick to blank and the shutter is kept open rather than shut for good
+pub fn strobe_shutter(
+    strobe: Res<Strobe>,
+    mode: Res<TickMode>,
+    fixed_time: Res<FixedTime>,
+    mut query: Query<&mut Visibility, With<StrobeShutter>>,
+) {
+    let progress = fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32();
+    let visibility = if *mode == TickMode::PerFrame || strobe.is_open(progress) {
+        Visibility::Hidden
+    } else {
+        Visibility::Visible
+    };
+    for mut shutter in query.iter_mut() {
+        shutter.set_if_neq(visibility);
+    }
+}
@@ -1,15 +1,29 @@
 use std::f32::consts::PI;
 use std::ops::{Mul, Not};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::bluetooth::RotationInterval;
-use crate::camera::{reset_camera_controls, ColorSettings, VideoStream};
+use crate::camera::{
+    apply_color_settings, describe_format, reset_camera_controls, ColorSettings, FormatNotice,
+    PixelLayout, SourceOp, VideoSource, VideoStream,
+};
+use crate::capture::WINDOW_ONLY_LAYER;
 use crate::gui::CameraCrosshairTag;
+use crate::material::{FrameUpload, ShownFrame, VideoMaterial};
+use crate::pattern::TestPattern;
+use crate::profile::CameraProfiles;
+use crate::rotation::{directed_speed, PlatterPhase, ResponseCurve};
 use crate::setup::Settings;
+use crate::stats::CaptureStats;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 use nokhwa::pixel_format::RgbAFormat;
-use nokhwa::utils::{CameraFormat, FrameFormat, RequestedFormat, RequestedFormatType};
+use nokhwa::utils::{RequestedFormat, RequestedFormatType};
 
 pub const TOP_BAR_SIZE: u32 = 12;
+/// Used for the window size when there is no window, as when running headless.
+pub const HEADLESS_SIZE: f32 = 512.;
 
 #[derive(Component)]
 pub struct ZoetropeImage;
@@ -20,6 +34,29 @@ pub struct ZoetropeAnimationThresholdSpeed(pub i8);
 #[derive(Resource)]
 pub struct Slices(pub u8);
 
+/// What drives the fixed tick that the platter is turned on.
+#[derive(Resource, Debug, PartialEq, Copy, Clone, Default)]
+pub enum TickMode {
+    // a tick for every slice each second, so full speed is a turn a second
+    #[default]
+    PerSlice,
+    // a tick for every frame that is drawn, which is locked to the display with vsync
+    PerFrame,
+    // ticks this many times a second whatever the slice count
+    Fixed(f32),
+}
+
+impl TickMode {
+    /// How long a tick lasts, `None` when it follows the frames.
+    pub fn period(&self, slices: u8) -> Option<Duration> {
+        match self {
+            Self::PerSlice => Some(slice_period(slices)),
+            Self::PerFrame => None,
+            Self::Fixed(hz) => Some(Duration::from_secs_f32(1. / hz.max(1.))),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct RotationDirection {
     pub audio: Direction,
@@ -55,42 +92,141 @@ impl Mul<f32> for Direction {
     }
 }
 
+/// How the platter is turned when it is set up, facing away from the camera.
+pub fn platter_facing() -> Quat {
+    Transform::from_xyz(0., 0., -1.0)
+        .looking_at(Vec3::ZERO, Vec3::Y)
+        .rotation
+}
+
+/// How the platter is turned once the phase is taken into account.
+pub fn platter_rotation(phase: &PlatterPhase, slices: u8) -> Quat {
+    Quat::from_rotation_z(phase.displayed(slices)) * platter_facing()
+}
+
+/// Where the zoetrope sits in the window and how big it is.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Preset {
+    ReCenter,
+    SemiCircle,
+    Right,
+    Left,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [Self::ReCenter, Self::SemiCircle, Self::Right, Self::Left];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ReCenter => "Re-Center",
+            Self::SemiCircle => "Semi-Circle",
+            Self::Right => "Right",
+            Self::Left => "Left",
+        }
+    }
+
+    /// The radius of the zoetrope and where the camera goes for a window of this size.
+    pub fn layout(&self, width: f32, height: f32) -> (f32, Transform) {
+        let mut transform = Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y);
+        let radius = match self {
+            Self::ReCenter => (height / 2.).ceil() + TOP_BAR_SIZE as f32,
+            Self::SemiCircle => {
+                transform.translation.y = height / 2.0;
+                ((width / 2.0) * 0.99).ceil()
+            }
+            Self::Right | Self::Left => {
+                let location = (width / 2.0).ceil();
+                transform.translation.y = height / 2.0;
+                transform.translation.x = if *self == Self::Right {
+                    -location
+                } else {
+                    location
+                };
+                height.ceil()
+            }
+        };
+        (radius, transform)
+    }
+}
+
 pub fn zoetrope_setup(
     mut commands: Commands,
     // video_images: Res<VideoFrame>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut materials: ResMut<Assets<VideoMaterial>>,
     settings: Res<Settings>,
+    slices: Res<Slices>,
     server: Res<AssetServer>,
     windows: Query<&Window>,
-    color_settings: ResMut<ColorSettings>,
+    mut color_settings: ResMut<ColorSettings>,
+    mut format_notice: ResMut<FormatNotice>,
+    phase: Res<PlatterPhase>,
 ) {
-    let cam = VideoStream::new(
-        settings.camera.clone(),
-        RequestedFormat::new::<RgbAFormat>(RequestedFormatType::Closest(CameraFormat::new(
-            settings.resolution,
-            FrameFormat::MJPEG,
-            settings.frame_rate,
-        ))),
-    )
-    .unwrap();
+    let format = RequestedFormat::new::<RgbAFormat>(match settings.format {
+        Some(format) => RequestedFormatType::Closest(format),
+        None => RequestedFormatType::AbsoluteHighestResolution,
+    });
+    let source = settings.source.clone().unwrap_or(VideoSource::TestPattern);
+    // the profiles below go with whichever source actually opened
+    let (source, mut cam) = match source.open(format, slices.0) {
+        Ok(cam) => (source, cam),
+        Err(e) => {
+            error!("Could not open {}: {}", source.name(), e);
+            info!("Falling back to the test pattern");
+            let cam = VideoStream::new(TestPattern::new(slices.0))
+                .expect("The test pattern should always be available");
+            (VideoSource::TestPattern, cam)
+        }
+    };
+
+    // `Closest` quietly settles for something else when the camera refuses the chosen format
+    if let (Some(requested), Some(negotiated)) = (settings.format, cam.format) {
+        if requested != negotiated {
+            let notice = format!(
+                "Requested {} but the camera is running at {}",
+                describe_format(&requested),
+                describe_format(&negotiated)
+            );
+            warn!("{}", notice);
+            format_notice.0 = Some(notice);
+        }
+    }
 
-    reset_camera_controls(color_settings, &cam);
+    // the camera starts out as it was tuned last time, if it was ever saved
+    let profiles = CameraProfiles::load(&source.name());
+    match profiles.default_profile() {
+        Some(profile) => {
+            *color_settings = profile.color.clone();
+            apply_color_settings(&color_settings, &mut cam);
+            profile.apply_crop(&profiles.camera, &mut cam);
+        }
+        None => reset_camera_controls(color_settings, &mut cam),
+    }
+    commands.insert_resource(profiles);
+    // the counts are per source, so start them over
+    commands.insert_resource(CaptureStats::default());
+    commands.insert_resource(ShownFrame::default());
 
-    let size = (windows.single().height() / 2.).ceil() + TOP_BAR_SIZE as f32;
+    let height = windows
+        .get_single()
+        .map_or(HEADLESS_SIZE, |window| window.height());
+    let size = (height / 2.).ceil() + TOP_BAR_SIZE as f32;
 
     commands
         .spawn(Camera2dBundle {
             transform: Transform::from_xyz(0., 0., 100.0).looking_at(Vec3::ZERO, Vec3::Y),
             ..default()
         })
+        .insert(RenderLayers::from_layers(&[0, WINDOW_ONLY_LAYER]))
         .insert(cam);
 
     commands
         .spawn(bevy::sprite::MaterialMesh2dBundle {
             mesh: meshes.add(shape::Circle::new(size).into()).into(),
-            material: materials.add(ColorMaterial::from(Color::WHITE)),
-            transform: Transform::from_xyz(0., 0., -1.0).looking_at(Vec3::ZERO, Vec3::Y),
+            material: materials.add(VideoMaterial::default()),
+            // picks up where it was, so the calibration holds when the platter is set up again
+            transform: Transform::from_xyz(0., 0., -1.0)
+                .with_rotation(platter_rotation(&phase, slices.0)),
             ..default()
         })
         .insert(ZoetropeImage);
@@ -102,28 +238,86 @@ pub fn zoetrope_setup(
             visibility: Visibility::Hidden,
             ..default()
         })
+        // only there to line the camera up, so it is left out of captures
+        .insert(RenderLayers::layer(WINDOW_ONLY_LAYER))
         .insert(CameraCrosshairTag);
 }
 
+// everything made by `zoetrope_setup` is removed, dropping the `VideoStream` releases the camera so that
+// it can be picked again in the setup menu
+pub fn zoetrope_teardown(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<VideoStream>,
+            With<ZoetropeImage>,
+            With<CameraCrosshairTag>,
+        )>,
+    >,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// How long a fixed tick lasts, so that the platter goes round once a second at full speed.
+pub fn slice_period(slices: u8) -> Duration {
+    Duration::from_secs_f64(1. / slices.max(1) as f64)
+}
+
+// the slice count and tick mode can be changed while running, so the tick and anything drawn per slice
+// follow along. the accumulated time is kept so that the animation does not stutter when they change
+pub fn zoetrope_tick_changed(
+    slices: Res<Slices>,
+    mode: Res<TickMode>,
+    mut fixed_time: ResMut<FixedTime>,
+    cam_query: Query<&VideoStream>,
+) {
+    if slices.is_changed() {
+        for cam in cam_query.iter() {
+            cam.set_slices(slices.0);
+        }
+    }
+    if slices.is_changed() || mode.is_changed() {
+        if let Some(period) = mode.period(slices.0) {
+            fixed_time.period = period;
+        }
+    }
+}
+
+// runs after the time has been updated but before the fixed tick, setting the tick to last exactly as
+// long as the frame that just went by so that it happens once this frame. a frame that took no time at all
+// gets no tick rather than endless ones
+pub fn zoetrope_tick_per_frame(
+    mode: Res<TickMode>,
+    time: Res<Time>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    if *mode == TickMode::PerFrame {
+        *fixed_time = FixedTime::new(time.delta().max(Duration::from_micros(1)));
+    }
+}
+
 pub fn zoetrope_animation(
     mut query: Query<&mut Transform, With<ZoetropeImage>>,
+    cam_query: Query<&VideoStream>,
     rotation: Res<RotationInterval>,
     max: Res<ZoetropeAnimationThresholdSpeed>,
     slices: Res<Slices>,
     dir: Res<RotationDirection>,
+    curve: Res<ResponseCurve>,
+    mut phase: ResMut<PlatterPhase>,
 ) {
     for mut transform in query.iter_mut() {
-        let val: f32;
-        // rotation is an i8
-        // need to get it to an f32
-        if rotation.0 >= max.0 {
-            val = dir.animation * 1.0;
-        } else if rotation.0 <= -max.0 {
-            val = !dir.animation * 1.0;
-        } else {
-            val = (dir.animation * (rotation.0 as f32) / max.0 as f32).into();
+        let val = directed_speed(rotation.0, max.0, dir.animation, &curve);
+        // set from the phase rather than turned bit by bit, so that it never drifts off the slices
+        phase.advance(val, slices.0);
+        transform.rotation = platter_rotation(&phase, slices.0);
+        // pre-rendered sources move on by a frame for every slice that the image is rotated
+        for cam in cam_query.iter() {
+            let _ = cam.op_tx.send(SourceOp::Advance(val));
         }
-        transform.rotate_z((2. * PI / slices.0 as f32) * val);
     }
 }
 
@@ -154,26 +348,55 @@ pub fn zoetrope_animation_keyboard(
 pub fn zoetrope_next_camera_frame(
     cam_query: Query<&mut VideoStream>,
     mut images: ResMut<Assets<Image>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mat_query: Query<&Handle<ColorMaterial>, With<ZoetropeImage>>,
+    mut materials: ResMut<Assets<VideoMaterial>>,
+    mat_query: Query<&Handle<VideoMaterial>, With<ZoetropeImage>>,
+    mut stats: ResMut<CaptureStats>,
+    mut shown: ResMut<ShownFrame>,
+    upload: Res<FrameUpload>,
 ) {
     let camera = cam_query.single();
-    if let Some(image) = camera.image_rx.drain().last() {
-        let mat = mat_query.single();
-        if let Some(material) = materials.get_mut(&mat) {
-            material.texture = Some(images.add(image));
+    // the source thread swaps out a frame that was not taken in time, so there is only ever the newest
+    let Ok(frame) = camera.image_rx.try_recv() else {
+        return;
+    };
+    let mat = mat_query.single();
+    let image = Arc::new(frame.image);
+    let texture = materials
+        .get(mat)
+        .filter(|material| material.layout() == frame.layout)
+        .and_then(|material| material.texture.clone())
+        .filter(|texture| {
+            images.get(texture).is_some_and(|current| {
+                current.texture_descriptor.size == image.texture_descriptor.size
+                    && current.texture_descriptor.format == image.texture_descriptor.format
+            })
+        });
+    match texture {
+        // written straight into the texture on the gpu, touching the assets would make it again
+        Some(texture) => upload.send(texture, image.clone()),
+        // the first frame, or the source changed format, so the material gets a new texture. the old one
+        // is freed along with its handle
+        _ => {
+            if let Some(material) = materials.get_mut(mat) {
+                material.texture = Some(images.add((*image).clone()));
+                material.set_layout(frame.layout);
+            }
         }
     }
+    shown.image = Some(image);
+    shown.layout = frame.layout;
+    stats.record_displayed(frame.captured_at, frame.decode_time);
 }
 
 #[allow(dead_code)]
 pub fn zoetrope_next_frame_static(
-    mat_query: Query<&Handle<ColorMaterial>, With<ZoetropeImage>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mat_query: Query<&Handle<VideoMaterial>, With<ZoetropeImage>>,
+    mut materials: ResMut<Assets<VideoMaterial>>,
     server: Res<AssetServer>,
 ) {
     let mat = mat_query.single();
     if let Some(material) = materials.get_mut(&mat) {
         material.texture = Some(server.load("background.png"));
+        material.set_layout(PixelLayout::Rgba);
     }
 }
@@ -0,0 +1,302 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use bevy::prelude::{Quat, Vec2};
+use uhdrtz::prelude::*;
+
+const SLICES: u8 = 24;
+const SLICE: f32 = 2. * PI / SLICES as f32;
+
+fn assert_size(size: Vec2, radius: f32) {
+    assert!(
+        (size - Vec2::splat(radius * 2.)).abs().max_element() < 1e-3,
+        "guides are {} across, expected {}",
+        size,
+        radius * 2.
+    );
+}
+
+fn assert_angle(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-4,
+        "platter is at {} radians, expected {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn still_crank_leaves_the_platter() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.step(10);
+    assert_angle(zoetrope.platter_angle(), 0.);
+}
+
+#[test]
+fn full_crank_turns_a_slice_per_tick() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(20);
+    zoetrope.step(3);
+    assert_angle(zoetrope.platter_angle(), 3. * SLICE);
+}
+
+#[test]
+fn slow_crank_turns_part_of_a_slice() {
+    let mut zoetrope = Headless::new(SLICES);
+    // under the default threshold of 5
+    zoetrope.set_crank(2);
+    zoetrope.step(5);
+    assert_angle(zoetrope.platter_angle(), 5. * 0.4 * SLICE);
+}
+
+#[test]
+fn reversed_crank_turns_back() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(20);
+    zoetrope.step(4);
+    zoetrope.set_crank(-20);
+    zoetrope.step(6);
+    assert_angle(zoetrope.platter_angle(), -2. * SLICE);
+}
+
+#[test]
+fn frames_only_move_on_fixed_ticks() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(20);
+    for _ in 0..5 {
+        zoetrope.update();
+    }
+    assert_angle(zoetrope.platter_angle(), 0.);
+}
+
+#[test]
+fn a_revolution_comes_back_around() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(20);
+    zoetrope.step(SLICES as u32);
+    assert_angle(zoetrope.platter_angle(), 0.);
+}
+
+#[test]
+fn slices_can_change_while_running() {
+    let mut zoetrope = Headless::new(SLICES);
+    assert_eq!(zoetrope.tick_period(), Duration::from_secs_f64(1. / 24.));
+    zoetrope.set_slices(12);
+    assert_eq!(zoetrope.tick_period(), Duration::from_secs_f64(1. / 12.));
+    zoetrope.set_crank(20);
+    zoetrope.step(2);
+    assert_angle(zoetrope.platter_angle(), 2. * 2. * PI / 12.);
+}
+
+#[test]
+fn fixed_rate_ignores_the_slices() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_tick_mode(TickMode::Fixed(100.));
+    zoetrope.set_crank(20);
+    zoetrope.update_after(Duration::from_millis(55));
+    assert_angle(zoetrope.platter_angle(), 5. * SLICE);
+}
+
+#[test]
+fn per_frame_mode_turns_a_slice_every_frame() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_tick_mode(TickMode::PerFrame);
+    zoetrope.set_crank(20);
+    // both far shorter and far longer than a slice's worth of time
+    zoetrope.update_after(Duration::from_millis(7));
+    zoetrope.update_after(Duration::from_millis(200));
+    zoetrope.update_after(Duration::from_millis(16));
+    assert_angle(zoetrope.platter_angle(), 3. * SLICE);
+}
+
+#[test]
+fn strobe_follows_the_ticks() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_strobe(Strobe {
+        enabled: true,
+        duty: 0.5,
+        phase: 0.,
+    });
+    // a tick is just under 42ms, so the first half of it is shown and the second half blanked
+    zoetrope.update_after(Duration::from_millis(5));
+    assert!(!zoetrope.strobe_blanked());
+    zoetrope.update_after(Duration::from_millis(20));
+    assert!(zoetrope.strobe_blanked());
+    // the next tick opens it again
+    zoetrope.update_after(Duration::from_millis(20));
+    assert!(!zoetrope.strobe_blanked());
+}
+
+#[test]
+fn strobe_phase_shifts_the_blanking() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_strobe(Strobe {
+        enabled: true,
+        duty: 0.5,
+        phase: 0.5,
+    });
+    zoetrope.update_after(Duration::from_millis(5));
+    assert!(zoetrope.strobe_blanked());
+    zoetrope.update_after(Duration::from_millis(20));
+    assert!(!zoetrope.strobe_blanked());
+}
+
+#[test]
+fn strobe_stays_open_when_ticking_every_frame() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_tick_mode(TickMode::PerFrame);
+    // would be shut for the whole of a tick that never has any time left over
+    zoetrope.set_strobe(Strobe {
+        enabled: true,
+        duty: 0.2,
+        phase: 0.5,
+    });
+    for _ in 0..5 {
+        zoetrope.update_after(Duration::from_millis(16));
+        assert!(!zoetrope.strobe_blanked());
+    }
+}
+
+#[test]
+fn strobe_is_off_by_default() {
+    let mut zoetrope = Headless::new(SLICES);
+    for _ in 0..10 {
+        zoetrope.update_after(Duration::from_millis(7));
+        assert!(!zoetrope.strobe_blanked());
+    }
+}
+
+#[test]
+fn slice_guides_turn_with_the_platter() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.update();
+    assert!(zoetrope.overlay().is_none());
+
+    zoetrope.set_overlay(SliceOverlay {
+        enabled: true,
+        ..Default::default()
+    });
+    zoetrope.set_crank(20);
+    zoetrope.step(5);
+    zoetrope.update();
+    let (transform, size) = zoetrope.overlay().expect("the guides should be shown");
+    let (axis, angle) = transform.rotation.to_axis_angle();
+    assert_angle(angle.copysign(axis.z), zoetrope.platter_angle());
+    // as wide as the platter, which starts out centered
+    let (radius, _) = Preset::ReCenter.layout(HEADLESS_SIZE, HEADLESS_SIZE);
+    assert_size(size, radius);
+}
+
+#[test]
+fn slice_guides_can_stay_still() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_overlay(SliceOverlay {
+        enabled: true,
+        follow: OverlayFollow::Camera,
+        rings: 2,
+    });
+    zoetrope.set_crank(20);
+    zoetrope.step(5);
+    zoetrope.update();
+    let (transform, _) = zoetrope.overlay().expect("the guides should be shown");
+    assert_eq!(transform.rotation, Quat::IDENTITY);
+
+    // and follow the platter to wherever a preset puts it
+    let radius = zoetrope.apply_preset(Preset::Right);
+    zoetrope.update();
+    let (_, size) = zoetrope.overlay().expect("the guides should be shown");
+    assert_size(size, radius);
+}
+
+#[test]
+fn reference_holds_the_platter_where_it_is() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(20);
+    zoetrope.step(5);
+    zoetrope.phase_mut().set_reference(SLICES);
+    assert_eq!(zoetrope.phase_mut().slice(SLICES), 0);
+    zoetrope.set_crank(0);
+    zoetrope.step(1);
+    assert_angle(zoetrope.platter_angle(), 5. * SLICE);
+}
+
+#[test]
+fn nudges_move_the_platter_without_cranking() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.phase_mut().nudge(-0.5_f32.to_radians());
+    zoetrope.step(1);
+    assert_angle(zoetrope.platter_angle(), -0.5_f32.to_radians());
+    zoetrope.phase_mut().reset();
+    zoetrope.step(1);
+    assert_angle(zoetrope.platter_angle(), 0.);
+}
+
+#[test]
+fn snapping_only_shows_whole_slices() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.phase_mut().snap = true;
+    // under the threshold, so each tick is 0.4 of a slice
+    zoetrope.set_crank(2);
+    zoetrope.step(1);
+    assert_angle(zoetrope.platter_angle(), 0.);
+    zoetrope.step(1);
+    assert_angle(zoetrope.platter_angle(), SLICE);
+    zoetrope.step(3);
+    assert_angle(zoetrope.platter_angle(), 2. * SLICE);
+}
+
+#[test]
+fn long_runs_stay_on_the_slices() {
+    let mut zoetrope = Headless::new(SLICES);
+    zoetrope.set_crank(2);
+    zoetrope.step(SLICES as u32 * 50);
+    // 0.4 of a slice at a time for 20 revolutions lands back where it started
+    assert_angle(zoetrope.platter_angle(), 0.);
+}
+
+#[test]
+fn presets_move_the_camera() {
+    let mut zoetrope = Headless::new(SLICES);
+    let half = HEADLESS_SIZE / 2.;
+
+    zoetrope.apply_preset(Preset::Right);
+    let camera = zoetrope.camera_transform().translation;
+    assert_eq!((camera.x, camera.y), (-half, half));
+
+    zoetrope.apply_preset(Preset::Left);
+    let camera = zoetrope.camera_transform().translation;
+    assert_eq!((camera.x, camera.y), (half, half));
+
+    zoetrope.apply_preset(Preset::SemiCircle);
+    let camera = zoetrope.camera_transform().translation;
+    assert_eq!((camera.x, camera.y), (0., half));
+
+    let radius = zoetrope.apply_preset(Preset::ReCenter);
+    let camera = zoetrope.camera_transform().translation;
+    assert_eq!((camera.x, camera.y), (0., 0.));
+    assert!(radius > half);
+}
+
+#[test]
+fn test_pattern_reaches_the_platter() {
+    let mut zoetrope = Headless::new(SLICES);
+    assert!(zoetrope.wait_for_frame(Duration::from_secs(10)));
+}
+
+#[test]
+#[ignore = "needs a gpu adapter"]
+fn renders_the_platter() {
+    let mut zoetrope = Headless::with_rendering(SLICES);
+    assert!(zoetrope.wait_for_frame(Duration::from_secs(10)));
+    let output = zoetrope
+        .render_output(Duration::from_secs(10))
+        .expect("the output should be read back");
+    assert_eq!(
+        output.dimensions(),
+        (HEADLESS_SIZE as u32, HEADLESS_SIZE as u32)
+    );
+    // the corners are outside the platter and left at the clear color
+    assert_eq!(output.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    let center = output.get_pixel(output.width() / 2, output.height() / 2).0;
+    assert_ne!(&center[..3], &[0, 0, 0]);
+}